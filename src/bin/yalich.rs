@@ -1,20 +1,21 @@
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use log::{error, info, warn};
 use reqwest::blocking::ClientBuilder;
-use serde::de::DeserializeOwned;
 use structopt::StructOpt;
 
 use yalich::{
-    core::{Config, DependencyNames, Resolve},
+    core::{
+        load_toml_file, Config, Dependency, DependencySpec, DependencySpecs, Exclusion, Language,
+        Resolve,
+    },
     github::{self, Github},
-    node::{self, npmjs::NpmJs, packagejson::PackageJson},
-    python::{self, pypi::PyPI, pyproject::PyProject},
-    rust::{self, cargo::Cargo, cratesio::CratesIo},
+    node::{self, npmjs::NpmJs},
+    python::{self, pypi::PyPI},
+    rust::{self, cratesio::CratesIo, vendor::Vendor},
 };
 
 /// yalich collects license metadata from a variety of dependencies.
@@ -25,57 +26,91 @@ pub struct Args {
     pub config: PathBuf,
 }
 
-fn load_file(path: &PathBuf) -> Result<String> {
-    let mut buffer = String::new();
-    let mut file = File::open(path).with_context(|| format!("Loading file {}", path.display()))?;
-    file.read_to_string(&mut buffer)?;
-    Ok(buffer)
-}
-
-fn load_toml_file<T: DeserializeOwned>(path: &PathBuf) -> Result<T> {
-    let buffer = load_file(path)?;
-    toml::from_str(&buffer).with_context(|| format!("With path {}", path.display()))
-}
-
-fn load_json_file<T: DeserializeOwned>(path: &PathBuf) -> Result<T> {
-    let buffer = load_file(path)?;
-    serde_json::from_str(&buffer).with_context(|| format!("With path {}", path.display()))
-}
-
-fn load_pyproject(path: &PathBuf) -> Result<PyProject> {
-    load_toml_file(path)
-}
-
-fn load_cargo(path: &PathBuf) -> Result<Cargo> {
-    load_toml_file(path)
-}
-
-fn load_packagejson(path: &PathBuf) -> Result<PackageJson> {
-    load_json_file(path)
-}
-
-fn load_package_names<T: DependencyNames>(
-    manifest_paths: &[PathBuf],
-    loader: impl Fn(&PathBuf) -> Result<T>,
-) -> Result<Vec<String>> {
-    let mut package_names: HashSet<String> = Default::default();
-    for manifest_path in manifest_paths {
+fn load_package_specs<T: DependencySpecs>(
+    category: &'static str,
+    language: &Language,
+    exclusions: &mut Vec<Exclusion>,
+    mut loader: impl FnMut(&Path) -> Result<T>,
+) -> Result<Vec<DependencySpec>> {
+    let mut package_specs: BTreeSet<DependencySpec> = Default::default();
+    let mut ignored_specs: BTreeSet<(DependencySpec, String)> = Default::default();
+    for manifest_path in &language.manifests {
         info!("Loading manifest {}", manifest_path.display());
         let manifest: T = loader(manifest_path)?;
-        for dependency_name in manifest.dependency_names() {
-            package_names.insert(dependency_name.to_owned());
+        for spec in manifest
+            .dependency_specs()
+            .filter(|spec| language.scopes.contains(&spec.scope))
+        {
+            match language.ignore_rule(&spec) {
+                Some(rule) => {
+                    info!(
+                        "Ignoring {} dependency '{}': {}",
+                        category, spec.name, rule.reason
+                    );
+                    ignored_specs.insert((spec, rule.reason.to_owned()));
+                }
+                None => {
+                    package_specs.insert(spec);
+                }
+            }
         }
     }
-    let mut python_packages: Vec<String> = package_names.into_iter().collect();
-    python_packages.sort();
-    Ok(python_packages)
+    exclusions.extend(ignored_specs.into_iter().map(|(spec, reason)| Exclusion {
+        category,
+        name: spec.name,
+        version: spec.version.or(spec.requirement),
+        scope: spec.scope,
+        workspace: spec.workspace,
+        reason,
+    }));
+    Ok(package_specs.into_iter().collect())
+}
+
+/// Resolve each package once, however many workspace members or scopes require it, and report
+/// it for each of them.
+fn resolve_package_specs(
+    specs: &[DependencySpec],
+    mut resolve: impl FnMut(&DependencySpec) -> Result<Dependency>,
+) -> Result<Vec<Dependency>> {
+    let mut resolved: HashMap<DependencySpec, Dependency> = HashMap::new();
+    let mut dependencies = Vec::new();
+    for spec in specs {
+        let package = DependencySpec {
+            alias: None,
+            scope: Default::default(),
+            workspace: None,
+            ..spec.clone()
+        };
+        let dependency = match resolved.get(&package) {
+            Some(dependency) => dependency.clone(),
+            None => {
+                let dependency = resolve(spec)?;
+                resolved.insert(package, dependency.clone());
+                dependency
+            }
+        };
+        dependencies.push(Dependency {
+            alias: spec.alias.to_owned(),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            ..dependency
+        });
+    }
+    Ok(dependencies)
 }
 
 fn run() -> Result<()> {
     let args = Args::from_args();
-    let config: Config = load_toml_file(&args.config)?;
-
-    let client = ClientBuilder::new().user_agent(config.user_agent).build()?;
+    let mut config: Config = load_toml_file(&args.config)?;
+    config
+        .languages
+        .node
+        .ignore
+        .extend(node::local_ignore_rules());
+
+    let client = ClientBuilder::new()
+        .user_agent(&config.user_agent)
+        .build()?;
     let mut writer = csv::Writer::from_writer(io::stdout());
 
     // Setup API clients
@@ -83,31 +118,97 @@ fn run() -> Result<()> {
     let pypi = PyPI::new(&client);
     let npmjs = NpmJs::new(&client);
     let github = Github::new(&client);
+    let rust_registries = rust::load_indexes(&client, &config.languages.rust)?;
 
     // Setup package name resolvers
-    let python_resolver = python::Resolver::new(&config.languages.python.overrides, &pypi);
-    let rust_resolver = rust::Resolver::new(&config.languages.rust.overrides, &cratesio);
+    let rust_resolver = rust::Resolver::new(
+        &config.languages.rust.language.overrides,
+        &cratesio,
+        &rust_registries,
+        &github,
+    );
+    let rust_vendor = Vendor::new(&config.languages.rust.vendor)?;
+    let rust_resolver = if config.languages.rust.vendor.is_empty() {
+        rust_resolver
+    } else {
+        rust_resolver.with_vendor(&rust_vendor)
+    };
     let node_resolver = node::Resolver::new(&config.languages.node.overrides, &npmjs);
     let github_enricher = github::Enricher::new(&github);
 
     // Load package names
-    let python_packages = load_package_names(&config.languages.python.manifests, load_pyproject)?;
-    let rust_packages = load_package_names(&config.languages.rust.manifests, load_cargo)?;
-    let node_packages = load_package_names(&config.languages.node.manifests, load_packagejson)?;
-
-    // Fetch metadata
-    let dependencies: Vec<_> = python_packages
-        .iter()
-        .map(|name| python_resolver.resolve(name))
-        .chain(rust_packages.iter().map(|name| rust_resolver.resolve(name)))
-        .chain(node_packages.iter().map(|name| node_resolver.resolve(name)))
-        .collect::<Result<_>>()?;
-
-    // Fallback to Github if required after first pass
-    let dependencies: Vec<_> = dependencies
-        .into_iter()
-        .map(|dependency| github_enricher.enrich(dependency))
-        .collect::<Result<_>>()?;
+    let mut exclusions = Vec::new();
+    let mut python_extra_index_urls = Vec::new();
+    let python_packages = load_package_specs(
+        "python",
+        &config.languages.python.language,
+        &mut exclusions,
+        |path| {
+            let manifest = python::Manifest::load(path, &config.languages.python.language)?;
+            python_extra_index_urls.extend_from_slice(manifest.extra_index_urls());
+            Ok(manifest)
+        },
+    )?;
+    let rust_packages = load_package_specs(
+        "rust",
+        &config.languages.rust.language,
+        &mut exclusions,
+        |path| rust::Manifest::load(path, &config.languages.rust),
+    )?;
+    let node_packages =
+        load_package_specs("node", &config.languages.node, &mut exclusions, |path| {
+            node::Manifest::load(path, &config.languages.node)
+        })?;
+
+    // List ignored packages separately, so that they can be reviewed
+    if let Some(excluded_path) = &config.excluded {
+        let mut excluded_writer = csv::Writer::from_path(excluded_path)
+            .with_context(|| format!("Creating file {}", excluded_path.display()))?;
+        for exclusion in exclusions {
+            excluded_writer
+                .serialize(exclusion)
+                .with_context(|| "CSV serialization failed".to_owned())?;
+        }
+        excluded_writer.flush()?;
+    }
+
+    // Python indexes may be named by the manifests themselves
+    let python_indexes = python::load_indexes(
+        &client,
+        &config.languages.python.language,
+        &python_packages,
+        &python_extra_index_urls,
+    )?;
+    let python_resolver = python::Resolver::new(
+        &config.languages.python.language.overrides,
+        &pypi,
+        &python_indexes,
+    );
+    let python_resolver = match &config.languages.python.license_dir {
+        Some(license_dir) => python_resolver.with_license_dir(license_dir),
+        None => python_resolver,
+    };
+
+    // Fetch metadata, falling back to Github if required after the first pass, except for
+    // cargo metadata and vendored crates which are read without network access
+    let enrich = |spec: &DependencySpec, dependency: Dependency| {
+        let offline = spec.metadata.is_some()
+            || (dependency.category == "rust" && !config.languages.rust.vendor.is_empty());
+        if offline {
+            Ok(dependency)
+        } else {
+            github_enricher.enrich(dependency)
+        }
+    };
+    let mut dependencies = resolve_package_specs(&python_packages, |spec| {
+        enrich(spec, python_resolver.resolve(spec)?)
+    })?;
+    dependencies.extend(resolve_package_specs(&rust_packages, |spec| {
+        enrich(spec, rust_resolver.resolve(spec)?)
+    })?);
+    dependencies.extend(resolve_package_specs(&node_packages, |spec| {
+        enrich(spec, node_resolver.resolve(spec)?)
+    })?);
 
     // Send final dependencies to writer
     for dependency in dependencies {
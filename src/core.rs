@@ -1,21 +1,194 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::hash::Hash;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use glob::Pattern;
+use serde::de::{DeserializeOwned, Deserializer, Error as _};
+use serde::Deserialize as _;
 use serde_derive::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Dependency {
     pub category: &'static str,
     pub name: String,
+    pub alias: Option<String>,
+    pub version: Option<String>,
+    pub scope: Scope,
+    /// Workspace member which declares the dependency.
+    pub workspace: Option<String>,
     pub url: String,
     pub license: Option<String>,
+    /// Path to a license file, for packages which don't name their license.
+    pub license_file: Option<String>,
     #[serde(skip_serializing)]
     pub homepage: Option<String>,
 }
 
-pub trait DependencyNames {
-    fn dependency_names<'a>(&'a self) -> Box<dyn Iterator<Item = &'a str> + 'a>;
+/// A dependency left out of the report by an ignore rule.
+#[derive(Serialize)]
+pub struct Exclusion {
+    pub category: &'static str,
+    pub name: String,
+    pub version: Option<String>,
+    pub scope: Scope,
+    pub workspace: Option<String>,
+    pub reason: String,
+}
+
+/// Why a dependency is required, which may carry different obligations.
+///
+/// Scopes are ordered by precedence, so a package required in several ways takes the first.
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Shipped as part of the package.
+    #[default]
+    Normal,
+    /// Shipped only when requested, such as an optional crate or a Python extra.
+    Optional,
+    /// Build scripts and build tooling.
+    Build,
+    /// Development and test tooling.
+    Dev,
+    /// Expected to be provided by the consumer of the package.
+    Peer,
+}
+
+impl Scope {
+    /// The scope of an optional dependency of a package in this scope.
+    pub fn optional(self) -> Self {
+        match self {
+            Scope::Normal => Scope::Optional,
+            scope => scope,
+        }
+    }
+}
+
+/// Walk a locked dependency graph breadth first from its roots, finding the scope of each
+/// package reached.
+///
+/// `dependencies` lists the packages a package depends on, with the scope they are reached
+/// with. A package reached through several paths takes the scope with the highest precedence.
+pub fn walk_scopes<K, I>(
+    roots: impl IntoIterator<Item = (K, Scope)>,
+    mut dependencies: impl FnMut(K, Scope) -> I,
+) -> HashMap<K, Scope>
+where
+    K: Copy + Eq + Hash,
+    I: IntoIterator<Item = (K, Scope)>,
+{
+    let mut queue: VecDeque<(K, Scope)> = roots.into_iter().collect();
+    let mut scopes: HashMap<K, Scope> = HashMap::new();
+    while let Some((key, scope)) = queue.pop_front() {
+        if let Some(existing) = scopes.get(&key) {
+            if *existing <= scope {
+                continue;
+            }
+        }
+        scopes.insert(key, scope);
+        queue.extend(dependencies(key, scope));
+    }
+    scopes
+}
+
+/// Where a dependency is published.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Source {
+    /// The public registry of the language.
+    #[default]
+    Default,
+    /// An alternative registry, by configured name or index URL.
+    Registry(String),
+    /// A git repository, optionally at a branch, tag or commit.
+    Git {
+        url: String,
+        reference: Option<String>,
+    },
+}
+
+/// License metadata already known from a manifest, so no lookup is needed.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Metadata {
+    pub license: Option<String>,
+    pub license_file: Option<String>,
+    pub homepage: Option<String>,
+}
+
+/// A dependency as declared in a manifest or pinned in a lockfile.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DependencySpec {
+    /// Name of the package in its registry.
+    pub name: String,
+    /// Local name of the package, if it has been renamed by the manifest.
+    pub alias: Option<String>,
+    /// Exact version, if pinned by a lockfile.
+    pub version: Option<String>,
+    /// Version requirement, if declared by a manifest.
+    pub requirement: Option<String>,
+    pub scope: Scope,
+    /// Workspace member which declares the dependency, if not the manifest itself.
+    pub workspace: Option<String>,
+    pub source: Source,
+    pub metadata: Option<Metadata>,
+}
+
+impl DependencySpec {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            alias: None,
+            version: None,
+            requirement: None,
+            scope: Scope::default(),
+            workspace: None,
+            source: Source::default(),
+            metadata: None,
+        }
+    }
+
+    pub fn with_alias(mut self, alias: &str) -> Self {
+        self.alias = Some(alias.to_owned());
+        self
+    }
+
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_owned());
+        self
+    }
+
+    pub fn with_requirement(mut self, requirement: &str) -> Self {
+        self.requirement = Some(requirement.to_owned());
+        self
+    }
+
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    pub fn with_workspace(mut self, workspace: &str) -> Self {
+        self.workspace = Some(workspace.to_owned());
+        self
+    }
+
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+}
+
+pub trait DependencySpecs {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a>;
 }
 
 pub trait FetchDependency<T> {
@@ -29,19 +202,145 @@ pub struct DependencyOverride {
 }
 pub type DependencyOverrides = HashMap<String, DependencyOverride>;
 
-#[derive(Deserialize, Debug, Default)]
+/// An alternative package registry.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Registry {
+    /// Index URL of the registry.
+    pub index: String,
+    /// Token sent in the `Authorization` header.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Username for basic authentication, with the token as the password.
+    #[serde(default)]
+    pub username: Option<String>,
+}
+pub type Registries = HashMap<String, Registry>;
+
+fn deserialize_pattern<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Pattern>, D::Error> {
+    let pattern: Option<String> = Option::deserialize(deserializer)?;
+    pattern
+        .map(|pattern| Pattern::new(&pattern).map_err(D::Error::custom))
+        .transpose()
+}
+
+/// A rule leaving dependencies out of the report, such as private or local packages.
+///
+/// A dependency is ignored if it matches every pattern given by the rule.
+#[derive(Deserialize, Debug, Clone)]
+pub struct IgnoreRule {
+    /// Glob matching the package name.
+    #[serde(default, deserialize_with = "deserialize_pattern")]
+    pub name: Option<Pattern>,
+    /// Prefix of the package name, such as an npm scope like `@fortawesome/`.
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Glob matching the version requirement declared by a manifest, or the locked version.
+    #[serde(default, deserialize_with = "deserialize_pattern")]
+    pub version: Option<Pattern>,
+    /// Why the dependency is ignored, which is listed with it.
+    pub reason: String,
+}
+
+impl IgnoreRule {
+    pub fn matches(&self, spec: &DependencySpec) -> bool {
+        let version = spec.requirement.as_ref().or(spec.version.as_ref());
+        let has_patterns = self.name.is_some() || self.prefix.is_some() || self.version.is_some();
+        has_patterns
+            && self.name.iter().all(|pattern| pattern.matches(&spec.name))
+            && self
+                .prefix
+                .iter()
+                .all(|prefix| spec.name.starts_with(prefix))
+            && self.version.iter().all(|pattern| {
+                version
+                    .map(|version| pattern.matches(version))
+                    .unwrap_or(false)
+            })
+    }
+}
+
+#[derive(Deserialize, Debug)]
 pub struct Language {
     pub manifests: Vec<PathBuf>,
     #[serde(default)]
     pub overrides: DependencyOverrides,
+    /// Read the lockfile belonging to each manifest, rather than the manifest itself.
+    #[serde(default)]
+    pub lockfile: bool,
+    /// Scopes of dependencies to include in the report.
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<Scope>,
+    /// Alternative registries, by name.
+    #[serde(default)]
+    pub registries: Registries,
+    /// Rules for dependencies to leave out of the report.
+    #[serde(default)]
+    pub ignore: Vec<IgnoreRule>,
+}
+
+impl Language {
+    /// The first rule ignoring a dependency, if any.
+    pub fn ignore_rule(&self, spec: &DependencySpec) -> Option<&IgnoreRule> {
+        self.ignore.iter().find(|rule| rule.matches(spec))
+    }
+}
+
+fn default_scopes() -> Vec<Scope> {
+    vec![Scope::Normal, Scope::Optional]
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Self {
+            manifests: Default::default(),
+            overrides: Default::default(),
+            lockfile: false,
+            scopes: default_scopes(),
+            registries: Default::default(),
+            ignore: Default::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RustLanguage {
+    #[serde(flatten)]
+    pub language: Language,
+    /// Cargo configuration files to read additional `[registries]` from.
+    #[serde(default)]
+    pub cargo_config: Vec<PathBuf>,
+    /// Directories of crate sources, such as from `cargo vendor` or `~/.cargo/registry/src`.
+    /// If set, crates are read from here and no requests are made.
+    #[serde(default)]
+    pub vendor: Vec<PathBuf>,
+    /// Features enabled on each crate in `manifests`, deciding which optional dependencies are
+    /// reported. These follow the `cargo build` flags of the same names.
+    #[serde(default)]
+    pub features: Vec<String>,
+    #[serde(default)]
+    pub all_features: bool,
+    #[serde(default)]
+    pub no_default_features: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct PythonLanguage {
+    #[serde(flatten)]
+    pub language: Language,
+    /// Directory to save license files bundled with downloaded packages into, so that
+    /// they can be classified. Each is reported as the `license_file` of its package.
+    #[serde(default)]
+    pub license_dir: Option<PathBuf>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Languages {
     #[serde(default)]
-    pub python: Language,
+    pub python: PythonLanguage,
     #[serde(default)]
-    pub rust: Language,
+    pub rust: RustLanguage,
     #[serde(default)]
     pub node: Language,
 }
@@ -50,8 +349,58 @@ pub struct Languages {
 pub struct Config {
     pub languages: Languages,
     pub user_agent: String,
+    /// CSV file to list dependencies left out by ignore rules in, with the reason for each.
+    #[serde(default)]
+    pub excluded: Option<PathBuf>,
 }
 
 pub trait Resolve {
-    fn resolve(&self, name: &str) -> Result<Dependency>;
+    fn resolve(&self, spec: &DependencySpec) -> Result<Dependency>;
+}
+
+/// The name of the file at a path, or an empty string if it has none.
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The first of the given lockfiles beside a manifest, which may be one of them itself.
+pub fn find_lockfile(path: &Path, lockfile_names: &[&str]) -> Result<PathBuf> {
+    if lockfile_names.contains(&file_name(path).as_str()) {
+        return Ok(path.to_owned());
+    }
+    lockfile_names
+        .iter()
+        .map(|lockfile_name| path.with_file_name(lockfile_name))
+        .find(|lockfile_path| lockfile_path.is_file())
+        .ok_or_else(|| {
+            anyhow!(
+                "No {} found for {}",
+                lockfile_names.join(" or "),
+                path.display()
+            )
+        })
+}
+
+pub fn load_file(path: &Path) -> Result<String> {
+    let mut buffer = String::new();
+    let mut file = File::open(path).with_context(|| format!("Loading file {}", path.display()))?;
+    file.read_to_string(&mut buffer)?;
+    Ok(buffer)
+}
+
+pub fn load_toml_file<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let buffer = load_file(path)?;
+    toml::from_str(&buffer).with_context(|| format!("With path {}", path.display()))
+}
+
+pub fn load_json_file<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let buffer = load_file(path)?;
+    serde_json::from_str(&buffer).with_context(|| format!("With path {}", path.display()))
+}
+
+pub fn load_yaml_file<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let buffer = load_file(path)?;
+    serde_yaml::from_str(&buffer).with_context(|| format!("With path {}", path.display()))
 }
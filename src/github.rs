@@ -1,6 +1,7 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use log::debug;
 use reqwest::blocking::Client;
+use reqwest::header::ACCEPT;
 use serde_derive::Deserialize;
 use url::Url;
 
@@ -11,6 +12,20 @@ pub struct License {
     pub name: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct TreeEntry {
+    pub path: String,
+    pub r#type: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Tree {
+    pub tree: Vec<TreeEntry>,
+    /// Set when the repository has more entries than the API returns at once.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Repo {
     pub full_name: String,
@@ -47,10 +62,73 @@ impl<'a> Github<'a> {
                 )
             })
     }
+
+    /// Raw contents of a file, at the given branch, tag or commit.
+    pub fn contents(
+        &self,
+        organisation: &str,
+        repo: &str,
+        path: &str,
+        reference: Option<&str>,
+    ) -> Result<String> {
+        let mut url = Url::parse(&format!(
+            "https://api.github.com/repos/{}/{}/contents/{}",
+            organisation, repo, path,
+        ))
+        .with_context(|| format!("Invalid URL for Github API '{}/{}'.", organisation, repo))?;
+        if let Some(reference) = reference {
+            url.query_pairs_mut().append_pair("ref", reference);
+        }
+
+        self.client
+            .get(url)
+            .header(ACCEPT, "application/vnd.github.v3.raw")
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| {
+                format!(
+                    "Github request for '{}' in '{}/{}' failed.",
+                    path, organisation, repo
+                )
+            })?
+            .text()
+            .with_context(|| format!("Reading '{}' in '{}/{}' failed.", path, organisation, repo))
+    }
+
+    /// Every file in a repo, at the given branch, tag or commit.
+    pub fn tree(&self, organisation: &str, repo: &str, reference: Option<&str>) -> Result<Tree> {
+        let mut url = Url::parse("https://api.github.com/?recursive=1")
+            .with_context(|| format!("Invalid URL for Github API '{}/{}'.", organisation, repo))?;
+        // Branch names may contain `/` or `#`, so the reference is encoded as a single segment
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid URL for Github API '{}/{}'.", organisation, repo))?
+            .clear()
+            .extend(&[
+                "repos",
+                organisation,
+                repo,
+                "git",
+                "trees",
+                reference.unwrap_or("HEAD"),
+            ]);
+
+        self.client
+            .get(url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Github request for '{}/{}' failed.", organisation, repo))?
+            .json()
+            .with_context(|| {
+                format!(
+                    "JSON deserialization for '{}/{}' failed.",
+                    organisation, repo
+                )
+            })
+    }
 }
 
 /// Validate and extract repo from homepage url.
-fn homepage_to_repo(mut homepage: &str) -> Option<(&str, &str)> {
+pub fn homepage_to_repo(mut homepage: &str) -> Option<(&str, &str)> {
     homepage = homepage
         .split('#')
         .next()
@@ -60,12 +138,18 @@ fn homepage_to_repo(mut homepage: &str) -> Option<(&str, &str)> {
         homepage = &homepage[..homepage.len() - 1]
     };
 
+    // Clone URLs are often given in place of a homepage
+    if let Some(stripped) = homepage.strip_suffix(".git") {
+        homepage = stripped
+    };
+
     if !homepage.contains("github.com") {
         return None;
     };
 
-    let html_url_parts: Vec<_> = homepage.rsplitn(3, '/').collect();
-    if let Some(repo) = html_url_parts.get(0) {
+    // SSH clone URLs separate the host with a colon, as in `git@github.com:org/repo.git`
+    let html_url_parts: Vec<_> = homepage.rsplitn(3, ['/', ':']).collect();
+    if let Some(repo) = html_url_parts.first() {
         if let Some(organisation) = html_url_parts.get(1) {
             return Some((organisation, repo));
         }
@@ -89,7 +173,7 @@ impl<'a> Enricher<'a> {
             // And we have a homepage
             if let Some(homepage) = &dependency.homepage {
                 // And it's Github
-                if let Some((organisation, repo)) = homepage_to_repo(&homepage) {
+                if let Some((organisation, repo)) = homepage_to_repo(homepage) {
                     debug!("Falling back to Github for {}/{}", organisation, repo);
                     let repo = self.github.repo(organisation, repo)?;
                     if let Some(repo_license) = repo.license {
@@ -103,3 +187,29 @@ impl<'a> Enricher<'a> {
         Ok(dependency)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_repo_urls() {
+        assert_eq!(
+            homepage_to_repo("https://github.com/serde-rs/serde"),
+            Some(("serde-rs", "serde"))
+        );
+        assert_eq!(
+            homepage_to_repo("https://github.com/serde-rs/serde.git/#readme"),
+            Some(("serde-rs", "serde"))
+        );
+        assert_eq!(
+            homepage_to_repo("git+ssh://git@github.com/serde-rs/serde.git"),
+            Some(("serde-rs", "serde"))
+        );
+        assert_eq!(
+            homepage_to_repo("git@github.com:serde-rs/serde.git"),
+            Some(("serde-rs", "serde"))
+        );
+        assert_eq!(homepage_to_repo("https://serde.rs"), None);
+    }
+}
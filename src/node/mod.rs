@@ -1,11 +1,131 @@
+use std::path::Path;
+
 use anyhow::Result;
+use glob::Pattern;
+use log::{info, warn};
 
-use crate::core::{Dependency, DependencyOverrides, FetchDependency, Resolve};
+use crate::core::{
+    file_name, find_lockfile, load_json_file, load_yaml_file, Dependency, DependencyOverrides,
+    DependencySpec, DependencySpecs, FetchDependency, IgnoreRule, Language, Metadata, Resolve,
+    Source,
+};
 
 pub mod npmjs;
 pub mod packagejson;
+pub mod packagelock;
+pub mod pnpmlock;
+pub mod yarnlock;
+
+use npmjs::{package_url, NpmJs, Package, Version};
+use packagejson::PackageJson;
+use packagelock::PackageLock;
+use pnpmlock::PnpmLock;
+use yarnlock::YarnLock;
+
+const NPM_LOCKFILE_NAME: &str = "package-lock.json";
+const NPM_SHRINKWRAP_NAME: &str = "npm-shrinkwrap.json";
+const YARN_LOCKFILE_NAME: &str = "yarn.lock";
+const PNPM_LOCKFILE_NAME: &str = "pnpm-lock.yaml";
+/// Lockfiles which may belong to a `package.json`, in order of preference.
+const LOCKFILE_NAMES: [&str; 4] = [
+    NPM_SHRINKWRAP_NAME,
+    NPM_LOCKFILE_NAME,
+    YARN_LOCKFILE_NAME,
+    PNPM_LOCKFILE_NAME,
+];
+
+/// Requirements of packages installed from a path on disk, rather than published.
+const LOCAL_REQUIREMENTS: [&str; 5] = ["file:*", "link:*", "./*", "../*", "/*"];
+
+/// Rules ignoring packages installed from a path on disk, for use after any configured rules.
+pub fn local_ignore_rules() -> Vec<IgnoreRule> {
+    LOCAL_REQUIREMENTS
+        .iter()
+        .map(|requirement| IgnoreRule {
+            name: None,
+            prefix: None,
+            version: Pattern::new(requirement).ok(),
+            reason: "Installed from a local path".to_owned(),
+        })
+        .collect()
+}
+
+/// Split `<name>@<version>` into its parts, where the name may itself start with `@`.
+pub fn package_name(specifier: &str) -> (&str, Option<&str>) {
+    let start = usize::from(specifier.starts_with('@'));
+    match specifier[start..].find('@') {
+        Some(index) => (
+            &specifier[..start + index],
+            Some(&specifier[start + index + 1..]),
+        ),
+        None => (specifier, None),
+    }
+}
+
+/// The source of a package from where it was resolved, or `None` if it is on disk.
+pub fn resolved_source(resolved: &str) -> Option<Source> {
+    if resolved.starts_with("file:") || resolved.starts_with("link:") {
+        return None;
+    }
+    let git_url = if let Some(repository) = resolved.strip_prefix("github:") {
+        format!("https://github.com/{}", repository)
+    } else if let Some(url) = resolved.strip_prefix("git+") {
+        url.to_owned()
+    } else if resolved.starts_with("git://") {
+        resolved.to_owned()
+    } else {
+        return Some(Source::Default);
+    };
+    let (url, reference) = match git_url.split_once('#') {
+        Some((url, reference)) => (url.to_owned(), Some(reference.to_owned())),
+        None => (git_url, None),
+    };
+    Some(Source::Git { url, reference })
+}
+
+/// A Node manifest, either declaring dependencies or pinning them.
+pub enum Manifest {
+    PackageJson(PackageJson),
+    PackageLock(PackageLock),
+    YarnLock(YarnLock),
+    PnpmLock(PnpmLock),
+}
+
+impl Manifest {
+    /// Load the manifest at the given path, or the lockfile beside it if configured.
+    pub fn load(path: &Path, language: &Language) -> Result<Self> {
+        if language.lockfile {
+            let lockfile_path = find_lockfile(path, &LOCKFILE_NAMES)?;
+            if lockfile_path != path {
+                info!("Using lockfile {}", lockfile_path.display());
+            }
+            return Self::load_file(&lockfile_path);
+        }
+        Self::load_file(path)
+    }
 
-use npmjs::{NpmJs, Package};
+    fn load_file(path: &Path) -> Result<Self> {
+        match file_name(path).as_str() {
+            NPM_LOCKFILE_NAME | NPM_SHRINKWRAP_NAME => {
+                Ok(Manifest::PackageLock(load_json_file(path)?))
+            }
+            YARN_LOCKFILE_NAME => Ok(Manifest::YarnLock(YarnLock::load(path)?)),
+            PNPM_LOCKFILE_NAME => Ok(Manifest::PnpmLock(load_yaml_file(path)?)),
+            _ => Ok(Manifest::PackageJson(PackageJson::load(path)?)),
+        }
+    }
+}
+
+impl DependencySpecs for Manifest {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        match self {
+            Manifest::PackageJson(package_json) => package_json.dependency_specs(),
+            Manifest::PackageLock(package_lock) => package_lock.dependency_specs(),
+            Manifest::YarnLock(yarn_lock) => yarn_lock.dependency_specs(),
+            Manifest::PnpmLock(pnpm_lock) => pnpm_lock.dependency_specs(),
+        }
+    }
+}
 
 pub struct Resolver<'a> {
     overrides: &'a DependencyOverrides,
@@ -18,24 +138,89 @@ impl<'a> Resolver<'a> {
     }
 }
 
-impl<'a> Resolve for Resolver<'a> {
-    fn resolve(&self, name: &str) -> Result<Dependency> {
-        let package = self.npmjs.fetch_dependency(name)?;
+impl<'a> Resolver<'a> {
+    /// Use metadata already given by the manifest.
+    fn resolve_known(&self, spec: &DependencySpec) -> Dependency {
+        let Metadata {
+            license,
+            license_file,
+            homepage,
+        } = spec.metadata.to_owned().unwrap_or_default();
+        let url = match &spec.source {
+            Source::Git { url, .. } => url.to_owned(),
+            _ => package_url(&spec.name),
+        };
+
+        Dependency {
+            category: "node",
+            name: spec.name.to_owned(),
+            alias: spec.alias.to_owned(),
+            version: spec.version.to_owned(),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            url,
+            license,
+            license_file,
+            homepage,
+        }
+    }
+
+    /// Packages from git are not on npm, so fall back to the repository license.
+    fn resolve_git(&self, spec: &DependencySpec, url: &str) -> Dependency {
+        Dependency {
+            category: "node",
+            name: spec.name.to_owned(),
+            alias: spec.alias.to_owned(),
+            version: spec.version.to_owned(),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            url: url.to_owned(),
+            license: None,
+            license_file: None,
+            homepage: Some(url.to_owned()),
+        }
+    }
+
+    fn resolve_npmjs(&self, spec: &DependencySpec) -> Result<Dependency> {
+        let package = self.npmjs.fetch_dependency(&spec.name)?;
         let url = package.url();
-        let version = package.latest_version();
+        // Another release may be licensed differently, so never fall back to it
+        let version = package.version(spec.version.as_deref());
+        if version.is_none() {
+            warn!(
+                "No release {} found for node package '{}'",
+                spec.version.as_deref().unwrap_or("latest"),
+                spec.name
+            );
+        }
         let license = version
-            .get_license()
+            .and_then(Version::get_license)
             .map(|license| license.name().to_owned());
 
-        let homepage = version.homepage.to_owned();
+        let homepage = version.and_then(|version| version.homepage.to_owned());
         let Package { name, .. } = package;
 
-        let mut dependency = Dependency {
+        Ok(Dependency {
             category: "node",
             name,
+            alias: spec.alias.to_owned(),
+            version: spec.version.to_owned(),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
             url,
             license,
+            license_file: None,
             homepage,
+        })
+    }
+}
+
+impl<'a> Resolve for Resolver<'a> {
+    fn resolve(&self, spec: &DependencySpec) -> Result<Dependency> {
+        let mut dependency = match &spec.source {
+            _ if spec.metadata.is_some() => self.resolve_known(spec),
+            Source::Git { url, .. } => self.resolve_git(spec, url),
+            _ => self.resolve_npmjs(spec)?,
         };
 
         if let Some(dependency_override) = self.overrides.get(&dependency.name) {
@@ -39,7 +39,7 @@ impl OneOrMany {
     pub fn get_license(&self) -> Option<&License> {
         match self {
             OneOrMany::One(one) => Some(one),
-            OneOrMany::Many(many) => many.get(0),
+            OneOrMany::Many(many) => many.first(),
         }
     }
 }
@@ -56,12 +56,12 @@ pub struct Version {
 
 impl Version {
     pub fn get_license(&self) -> Option<&License> {
-        for field in [&self.license, &self.licenses].iter() {
-            if let Some(one_or_many) = field {
-                return one_or_many.get_license();
-            }
-        }
-        None
+        [&self.license, &self.licenses]
+            .iter()
+            .copied()
+            .flatten()
+            .next()
+            .and_then(OneOrMany::get_license)
     }
 }
 
@@ -79,15 +79,26 @@ pub struct Package {
     pub versions: HashMap<String, Version>,
 }
 
+/// The npm page for a package.
+pub fn package_url(package_name: &str) -> String {
+    format!("https://www.npmjs.com/package/{}", package_name)
+}
+
 impl Package {
     pub fn url(&self) -> String {
-        format!("https://www.npmjs.com/package/{}", self.name)
+        package_url(&self.name)
     }
 
-    pub fn latest_version(&self) -> &Version {
-        self.versions
-            .get(&self.dist_tags.latest)
-            .expect("Latest version has no metadata")
+    pub fn latest_version(&self) -> Option<&Version> {
+        self.versions.get(&self.dist_tags.latest)
+    }
+
+    /// The given version, or the latest if none is given, or `None` if it is not published.
+    pub fn version(&self, version: Option<&str>) -> Option<&Version> {
+        match version {
+            Some(version) => self.versions.get(version),
+            None => self.latest_version(),
+        }
     }
 }
 
@@ -1,24 +1,267 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
+use anyhow::{Context, Result};
+use log::debug;
 use serde_derive::Deserialize;
 
-use crate::core::DependencyNames;
+use crate::core::{load_json_file, DependencySpec, DependencySpecs, Scope};
+
+const MANIFEST_NAME: &str = "package.json";
+
+/// Workspace member globs, which Yarn classic also allows under `packages`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Workspaces {
+    Packages(Vec<String>),
+    Detailed {
+        #[serde(default)]
+        packages: Vec<String>,
+    },
+}
+
+impl Workspaces {
+    fn patterns(&self) -> &[String] {
+        match self {
+            Workspaces::Packages(packages) => packages,
+            Workspaces::Detailed { packages } => packages,
+        }
+    }
+}
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PackageJson {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub workspaces: Option<Workspaces>,
+    #[serde(default)]
     pub dependencies: HashMap<String, String>,
+    #[serde(default)]
+    pub dev_dependencies: HashMap<String, String>,
+    #[serde(default)]
+    pub peer_dependencies: HashMap<String, String>,
+    #[serde(default)]
+    pub optional_dependencies: HashMap<String, String>,
+    /// Workspace members, if this is a workspace root.
+    #[serde(skip)]
+    pub members: Vec<PackageJson>,
+    /// Name of this workspace member, or its directory if it has none.
+    #[serde(skip)]
+    pub workspace: Option<String>,
+    /// Names of every package in the workspace, which are not published.
+    #[serde(skip)]
+    pub workspace_names: HashSet<String>,
+}
+
+impl PackageJson {
+    /// Load a manifest, along with its workspace members.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut package_json: PackageJson = load_json_file(path)?;
+        let root = path.parent().unwrap_or_else(|| Path::new(""));
+        let member_paths = match &package_json.workspaces {
+            Some(workspaces) => member_paths(root, workspaces.patterns())?,
+            None => return Ok(package_json),
+        };
+
+        let mut members = Vec::new();
+        for member_path in member_paths {
+            debug!("Loading workspace member {}", member_path.display());
+            let mut member: PackageJson = load_json_file(&member_path)?;
+            let directory = member_path.parent().unwrap_or(root);
+            member.workspace = member.name.to_owned().or_else(|| {
+                Some(
+                    directory
+                        .strip_prefix(root)
+                        .unwrap_or(directory)
+                        .display()
+                        .to_string(),
+                )
+            });
+            members.push(member);
+        }
+
+        let workspace_names: HashSet<String> = package_json
+            .name
+            .iter()
+            .chain(members.iter().filter_map(|member| member.name.as_ref()))
+            .cloned()
+            .collect();
+        for member in &mut members {
+            member.workspace_names = workspace_names.clone();
+        }
+        package_json.workspace_names = workspace_names;
+        package_json.members = members;
+        Ok(package_json)
+    }
+
+    fn package_specs<'a>(
+        &'a self,
+        dependencies: &'a HashMap<String, String>,
+        scope: Scope,
+    ) -> impl Iterator<Item = DependencySpec> + 'a {
+        dependencies
+            .iter()
+            // filter out references to other workspace members
+            .filter(move |(name, spec)| {
+                !(spec.starts_with("workspace:") || self.workspace_names.contains(*name))
+            })
+            .map(move |(name, requirement)| {
+                let spec = DependencySpec::new(name)
+                    .with_requirement(requirement)
+                    .with_scope(scope);
+                match &self.workspace {
+                    Some(workspace) => spec.with_workspace(workspace),
+                    None => spec,
+                }
+            })
+    }
+}
+
+/// Expand member globs relative to the workspace root, dropping those negated with `!`.
+fn member_paths(root: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut member_paths = Vec::new();
+    let mut excluded = Vec::new();
+    for pattern in patterns {
+        let (pattern, paths) = match pattern.strip_prefix('!') {
+            Some(pattern) => (pattern, &mut excluded),
+            None => (pattern.as_str(), &mut member_paths),
+        };
+        let glob_pattern = root.join(pattern).join(MANIFEST_NAME);
+        let manifest_paths = glob::glob(&glob_pattern.to_string_lossy())
+            .with_context(|| format!("Invalid workspace glob '{}'", pattern))?;
+        for manifest_path in manifest_paths {
+            let manifest_path = manifest_path?;
+            // Installed packages may match a broad glob
+            if !manifest_path
+                .components()
+                .any(|component| component.as_os_str() == "node_modules")
+            {
+                paths.push(manifest_path);
+            }
+        }
+    }
+    member_paths.retain(|member_path| !excluded.contains(member_path));
+    member_paths.sort();
+    member_paths.dedup();
+    Ok(member_paths)
 }
 
-impl DependencyNames for PackageJson {
-    fn dependency_names<'a>(&'a self) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+impl DependencySpecs for PackageJson {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
         Box::new(
-            self.dependencies
-                .iter()
-                // filter out local package links
-                .filter(|(name, spec)| {
-                    !(spec.starts_with("../") || name.starts_with("@fortawesome/pro"))
-                })
-                .map(|(name, _spec)| name.as_ref()),
+            self.package_specs(&self.dependencies, Scope::Normal)
+                .chain(self.package_specs(&self.dev_dependencies, Scope::Dev))
+                .chain(self.package_specs(&self.peer_dependencies, Scope::Peer))
+                .chain(self.package_specs(&self.optional_dependencies, Scope::Optional))
+                .chain(
+                    self.members
+                        .iter()
+                        .flat_map(|member| member.dependency_specs()),
+                ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::node::local_ignore_rules;
+
+    #[test]
+    fn loads_workspace_members() {
+        let root = std::env::temp_dir().join(format!("yalich-workspaces-{}", std::process::id()));
+        let manifests = [
+            (
+                "package.json",
+                r#"{"name": "root", "workspaces": {"packages": ["packages/*", "!packages/c"]},
+                    "devDependencies": {"eslint": "^8"}}"#,
+            ),
+            (
+                "packages/a/package.json",
+                r#"{"name": "@me/a", "dependencies": {"react": "^18", "@me/b": "^1", "web": "workspace:*"}}"#,
+            ),
+            (
+                "packages/b/package.json",
+                r#"{"name": "@me/b", "peerDependencies": {"react": "^18"}}"#,
+            ),
+            (
+                "packages/c/package.json",
+                r#"{"name": "@me/c", "dependencies": {"excluded": "1"}}"#,
+            ),
+            (
+                "packages/web/package.json",
+                r#"{"optionalDependencies": {"fsevents": "^2"}}"#,
+            ),
+            (
+                "packages/a/node_modules/installed/package.json",
+                r#"{"name": "installed"}"#,
+            ),
+        ];
+        for (path, contents) in &manifests {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let package_json = PackageJson::load(&root.join(MANIFEST_NAME)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        let specs: Vec<_> = package_json.dependency_specs().collect();
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("eslint")
+                    .with_requirement("^8")
+                    .with_scope(Scope::Dev),
+                DependencySpec::new("react")
+                    .with_requirement("^18")
+                    .with_workspace("@me/a"),
+                DependencySpec::new("react")
+                    .with_requirement("^18")
+                    .with_scope(Scope::Peer)
+                    .with_workspace("@me/b"),
+                // Members without a name are known by their directory
+                DependencySpec::new("fsevents")
+                    .with_requirement("^2")
+                    .with_scope(Scope::Optional)
+                    .with_workspace("packages/web"),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_local_packages_to_ignore_rules() {
+        let package_json: PackageJson = serde_json::from_str(
+            r#"{"dependencies": {
+                "lib": "file:../lib",
+                "linked": "link:../linked",
+                "util": "../util",
+                "react": "^18",
+                "gitdep": "github:user/repo"
+            }}"#,
         )
+        .unwrap();
+        let rules = local_ignore_rules();
+        let mut ignored: Vec<_> = package_json
+            .dependency_specs()
+            .map(|spec| {
+                let ignored = rules.iter().any(|rule| rule.matches(&spec));
+                (spec.name, ignored)
+            })
+            .collect();
+        ignored.sort();
+        assert_eq!(
+            ignored,
+            [
+                ("gitdep".to_owned(), false),
+                ("lib".to_owned(), true),
+                ("linked".to_owned(), true),
+                ("react".to_owned(), false),
+                ("util".to_owned(), true),
+            ]
+        );
     }
 }
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use log::warn;
+use serde_derive::Deserialize;
+
+use crate::core::{DependencySpec, DependencySpecs, Metadata, Scope, Source};
+use crate::node::npmjs::OneOrMany;
+use crate::node::{package_name, resolved_source};
+
+/// An installed package in a v2 or v3 lockfile.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Package {
+    /// Published name, if installed under an alias.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub resolved: Option<String>,
+    #[serde(default)]
+    pub license: Option<OneOrMany>,
+    #[serde(default)]
+    pub link: bool,
+    #[serde(default)]
+    pub dev: bool,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default)]
+    pub dev_optional: bool,
+    #[serde(default)]
+    pub peer: bool,
+}
+
+/// An installed package in a v1 lockfile, with the packages nested beneath it.
+#[derive(Deserialize)]
+pub struct Dependency {
+    /// The exact version, or the `npm:`, git or `file:` specifier it was installed from.
+    pub version: String,
+    #[serde(default)]
+    pub dev: bool,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+/// npm's lockfile, pinning every installed package.
+///
+/// Version 1 nests packages under `dependencies`, while versions 2 and 3 list them
+/// under `packages` by their path in `node_modules`. Version 2 has both.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageLock {
+    #[serde(default)]
+    pub lockfile_version: u32,
+    #[serde(default)]
+    pub packages: BTreeMap<String, Package>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+fn scope(dev: bool, optional: bool) -> Scope {
+    match (dev, optional) {
+        (true, _) => Scope::Dev,
+        (false, true) => Scope::Optional,
+        (false, false) => Scope::Normal,
+    }
+}
+
+impl Package {
+    fn spec(&self, path: &str) -> Option<DependencySpec> {
+        // The root package and workspace members are not installed under node_modules
+        let (_, local_name) = path.rsplit_once("node_modules/")?;
+        if self.link {
+            return None;
+        }
+        let source = match &self.resolved {
+            Some(resolved) => resolved_source(resolved)?,
+            None => Default::default(),
+        };
+        let scope = match self {
+            Package { peer: true, .. } => Scope::Peer,
+            Package {
+                dev_optional: true, ..
+            } => Scope::Dev,
+            Package { dev, optional, .. } => scope(*dev, *optional),
+        };
+
+        let mut spec = match &self.name {
+            Some(name) if name != local_name => DependencySpec::new(name).with_alias(local_name),
+            _ => DependencySpec::new(local_name),
+        }
+        .with_scope(scope)
+        .with_source(source);
+        if let Some(version) = &self.version {
+            spec = spec.with_version(version);
+        }
+        if let Some(license) = self.license.as_ref().and_then(OneOrMany::get_license) {
+            spec = spec.with_metadata(Metadata {
+                license: Some(license.name().to_owned()),
+                ..Default::default()
+            });
+        }
+        Some(spec)
+    }
+}
+
+/// The version of a package installed from a tarball, as in `<name>-<version>.tgz`.
+fn tarball_version<'a>(local_name: &str, url: &'a str) -> Option<&'a str> {
+    let filename = url.split(['?', '#']).next()?.rsplit('/').next()?;
+    let unscoped_name = local_name.rsplit('/').next()?;
+    filename
+        .strip_prefix(unscoped_name)?
+        .strip_prefix('-')?
+        .strip_suffix(".tgz")
+        .filter(|version| !version.is_empty())
+}
+
+impl Dependency {
+    fn spec(&self, local_name: &str) -> Option<DependencySpec> {
+        let scope = scope(self.dev, self.optional);
+        // Aliases are recorded as `npm:<name>@<version>`
+        if let Some(aliased) = self.version.strip_prefix("npm:") {
+            let (name, version) = package_name(aliased);
+            let mut spec = DependencySpec::new(name)
+                .with_alias(local_name)
+                .with_scope(scope);
+            if let Some(version) = version {
+                spec = spec.with_version(version);
+            }
+            return Some(spec);
+        }
+
+        let spec = DependencySpec::new(local_name).with_scope(scope);
+        match resolved_source(&self.version)? {
+            Source::Default if !self.version.contains("://") => {
+                Some(spec.with_version(&self.version))
+            }
+            // Tarballs are recorded by URL, which usually names the version
+            Source::Default => match tarball_version(local_name, &self.version) {
+                Some(version) => Some(spec.with_version(version)),
+                None => {
+                    warn!(
+                        "Skipping node package '{}' installed from {}",
+                        local_name, self.version
+                    );
+                    None
+                }
+            },
+            source => Some(spec.with_source(source)),
+        }
+    }
+
+    fn dependency_specs<'a>(
+        dependencies: &'a BTreeMap<String, Dependency>,
+    ) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(dependencies.iter().flat_map(|(local_name, dependency)| {
+            dependency
+                .spec(local_name)
+                .into_iter()
+                .chain(Self::dependency_specs(&dependency.dependencies))
+        }))
+    }
+}
+
+impl DependencySpecs for PackageLock {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        if self.lockfile_version >= 2 || !self.packages.is_empty() {
+            Box::new(
+                self.packages
+                    .iter()
+                    .filter_map(|(path, package)| package.spec(path)),
+            )
+        } else {
+            Dependency::dependency_specs(&self.dependencies)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(lockfile: &str) -> Vec<DependencySpec> {
+        serde_json::from_str::<PackageLock>(lockfile)
+            .unwrap()
+            .dependency_specs()
+            .collect()
+    }
+
+    #[test]
+    fn reads_nested_dependencies() {
+        let specs = specs(
+            r#"{
+                "lockfileVersion": 1,
+                "dependencies": {
+                    "left-pad": {
+                        "version": "1.3.0",
+                        "dependencies": {"inner": {"version": "0.1.0", "dev": true}}
+                    },
+                    "my-alias": {"version": "npm:@scope/real@2.0.0", "optional": true},
+                    "gitdep": {"version": "github:user/repo#abc"},
+                    "local": {"version": "file:../local"}
+                }
+            }"#,
+        );
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("gitdep").with_source(Source::Git {
+                    url: "https://github.com/user/repo".to_owned(),
+                    reference: Some("abc".to_owned()),
+                }),
+                DependencySpec::new("left-pad").with_version("1.3.0"),
+                DependencySpec::new("inner")
+                    .with_version("0.1.0")
+                    .with_scope(Scope::Dev),
+                DependencySpec::new("@scope/real")
+                    .with_alias("my-alias")
+                    .with_version("2.0.0")
+                    .with_scope(Scope::Optional),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_tarball_versions() {
+        let specs = specs(
+            r#"{
+                "lockfileVersion": 1,
+                "dependencies": {
+                    "@scope/foo": {"version": "https://registry.example.com/@scope/foo/-/foo-1.2.3.tgz"},
+                    "bar": {"version": "https://example.com/download?id=1"}
+                }
+            }"#,
+        );
+        assert_eq!(
+            specs,
+            [DependencySpec::new("@scope/foo").with_version("1.2.3")]
+        );
+    }
+
+    #[test]
+    fn reads_installed_packages() {
+        let specs = specs(
+            r#"{
+                "lockfileVersion": 3,
+                "packages": {
+                    "": {"name": "app", "version": "1.0.0"},
+                    "packages/member": {"name": "member", "version": "1.0.0"},
+                    "node_modules/member": {"resolved": "packages/member", "link": true},
+                    "node_modules/foo": {"version": "1.0.0", "license": "MIT", "devOptional": true},
+                    "node_modules/foo/node_modules/bar": {"version": "2.0.0", "peer": true},
+                    "node_modules/my-alias": {"name": "real", "version": "3.0.0", "optional": true},
+                    "node_modules/gitdep": {"version": "1.0.0", "resolved": "git+ssh://git@github.com/user/repo.git#abc"},
+                    "node_modules/local": {"version": "1.0.0", "resolved": "file:../local"}
+                }
+            }"#,
+        );
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("foo")
+                    .with_version("1.0.0")
+                    .with_scope(Scope::Dev)
+                    .with_metadata(Metadata {
+                        license: Some("MIT".to_owned()),
+                        ..Default::default()
+                    }),
+                DependencySpec::new("bar")
+                    .with_version("2.0.0")
+                    .with_scope(Scope::Peer),
+                DependencySpec::new("gitdep")
+                    .with_version("1.0.0")
+                    .with_source(Source::Git {
+                        url: "ssh://git@github.com/user/repo.git".to_owned(),
+                        reference: Some("abc".to_owned()),
+                    }),
+                DependencySpec::new("real")
+                    .with_alias("my-alias")
+                    .with_version("3.0.0")
+                    .with_scope(Scope::Optional),
+            ]
+        );
+    }
+}
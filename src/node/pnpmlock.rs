@@ -0,0 +1,595 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_derive::Deserialize;
+
+use crate::core::{walk_scopes, DependencySpec, DependencySpecs, Scope, Source};
+use crate::node::package_name;
+
+/// A dependency of an importer, which is a bare version before lockfile v6.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ImporterDependency {
+    Version(String),
+    Detailed { version: String },
+}
+
+impl ImporterDependency {
+    fn version(&self) -> &str {
+        match self {
+            ImporterDependency::Version(version) => version,
+            ImporterDependency::Detailed { version } => version,
+        }
+    }
+}
+
+/// A project in the workspace, or the single project of a lockfile without `importers`.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Importer {
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, ImporterDependency>,
+    #[serde(default)]
+    pub dev_dependencies: BTreeMap<String, ImporterDependency>,
+    #[serde(default)]
+    pub optional_dependencies: BTreeMap<String, ImporterDependency>,
+}
+
+/// Where a package was installed from, if not the registry.
+#[derive(Deserialize, Default)]
+pub struct Resolution {
+    #[serde(default)]
+    pub r#type: Option<String>,
+    #[serde(default)]
+    pub repo: Option<String>,
+    #[serde(default)]
+    pub commit: Option<String>,
+    #[serde(default)]
+    pub directory: Option<String>,
+    #[serde(default)]
+    pub tarball: Option<String>,
+}
+
+impl Resolution {
+    fn source(&self) -> Source {
+        if let (Some("git"), Some(repo)) = (self.r#type.as_deref(), &self.repo) {
+            return Source::Git {
+                url: repo.to_owned(),
+                reference: self.commit.to_owned(),
+            };
+        }
+        // Dependencies on GitHub repositories are downloaded as an archive of the commit
+        let archive = self
+            .tarball
+            .as_deref()
+            .and_then(|tarball| tarball.strip_prefix("https://codeload.github.com/"))
+            .and_then(|archive| archive.split_once("/tar.gz/"));
+        match archive {
+            Some((repository, commit)) => Source::Git {
+                url: format!("https://github.com/{}", repository),
+                reference: Some(commit.to_owned()),
+            },
+            None => Source::Default,
+        }
+    }
+}
+
+/// Dependencies of a locked package, which are kept in `snapshots` from lockfile v9.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub optional_dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+pub struct Package {
+    /// Only given for packages not from the registry.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub resolution: Resolution,
+    #[serde(flatten)]
+    pub snapshot: Snapshot,
+}
+
+/// pnpm's lockfile, pinning every package in the workspace.
+///
+/// Packages are keyed like `/name/1.0.0` in v5, `/name@1.0.0` in v6 and `name@1.0.0` from v9,
+/// followed by any peer dependencies they were resolved with.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PnpmLock {
+    /// A number before v6, and a string since.
+    #[serde(default)]
+    pub lockfile_version: serde_yaml::Value,
+    #[serde(default)]
+    pub importers: BTreeMap<String, Importer>,
+    #[serde(flatten)]
+    pub root: Importer,
+    #[serde(default)]
+    pub packages: BTreeMap<String, Package>,
+    #[serde(default)]
+    pub snapshots: BTreeMap<String, Snapshot>,
+}
+
+/// Remove the peer dependencies a package was resolved with, as in `1.0.0(react@18.0.0)`.
+fn strip_peers(key: &str) -> &str {
+    key.split('(').next().unwrap_or(key)
+}
+
+impl PnpmLock {
+    fn major_version(&self) -> u32 {
+        let version = match &self.lockfile_version {
+            serde_yaml::Value::String(version) => version.to_owned(),
+            serde_yaml::Value::Number(version) => version.to_string(),
+            _ => return 0,
+        };
+        version
+            .split('.')
+            .next()
+            .and_then(|major| major.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Each project with the workspace it is reported as, which is its directory in a
+    /// workspace and `None` for the root.
+    fn importers(&self) -> Box<dyn Iterator<Item = (Option<&str>, &Importer)> + '_> {
+        if self.importers.is_empty() {
+            Box::new(std::iter::once((None, &self.root)))
+        } else {
+            Box::new(self.importers.iter().map(|(directory, importer)| {
+                let workspace = Some(directory.as_str()).filter(|directory| *directory != ".");
+                (workspace, importer)
+            }))
+        }
+    }
+
+    /// The dependencies of each package, by the key dependency references resolve to.
+    fn snapshots(&self) -> HashMap<&str, &Snapshot> {
+        if self.snapshots.is_empty() {
+            self.packages
+                .iter()
+                .map(|(key, package)| (key.as_str(), &package.snapshot))
+                .collect()
+        } else {
+            self.snapshots
+                .iter()
+                .map(|(key, snapshot)| (key.as_str(), snapshot))
+                .collect()
+        }
+    }
+
+    /// The key of a dependency, from its name and the version it references.
+    ///
+    /// Aliased and non-registry dependencies reference a key directly.
+    fn dependency_key<'a>(
+        &self,
+        snapshots: &HashMap<&'a str, &'a Snapshot>,
+        name: &str,
+        reference: &str,
+    ) -> Option<&'a str> {
+        let key = match self.major_version() {
+            0..=5 => format!("/{}/{}", name, reference),
+            6..=8 => format!("/{}@{}", name, reference),
+            _ => format!("{}@{}", name, reference),
+        };
+        snapshots
+            .get_key_value(key.as_str())
+            .or_else(|| snapshots.get_key_value(reference))
+            .map(|(key, _snapshot)| *key)
+    }
+
+    /// Walk the locked graph from each importer, finding the scope of each package in the
+    /// workspaces which require it.
+    fn package_scopes(&self) -> HashMap<(Option<&str>, &str), Scope> {
+        let snapshots = self.snapshots();
+        let dependency_key =
+            |name: &str, reference: &str| self.dependency_key(&snapshots, name, reference);
+        let roots = self.importers().flat_map(|(workspace, importer)| {
+            let dependencies = importer
+                .dependencies
+                .iter()
+                .map(|(name, dependency)| (name, dependency, Scope::Normal));
+            let optional_dependencies = importer
+                .optional_dependencies
+                .iter()
+                .map(|(name, dependency)| (name, dependency, Scope::Optional));
+            let dev_dependencies = importer
+                .dev_dependencies
+                .iter()
+                .map(|(name, dependency)| (name, dependency, Scope::Dev));
+            dependencies
+                .chain(optional_dependencies)
+                .chain(dev_dependencies)
+                .filter_map(move |(name, dependency, scope)| {
+                    let key = dependency_key(name, dependency.version())?;
+                    Some(((workspace, key), scope))
+                })
+        });
+        let scopes = walk_scopes(roots, |(workspace, key), scope| {
+            let snapshot = snapshots.get(key);
+            let dependencies = snapshot
+                .into_iter()
+                .flat_map(|snapshot| &snapshot.dependencies)
+                .map(|(name, reference)| (name, reference, scope));
+            let optional_dependencies = snapshot
+                .into_iter()
+                .flat_map(|snapshot| &snapshot.optional_dependencies)
+                .map(|(name, reference)| (name, reference, scope.optional()));
+            dependencies
+                .chain(optional_dependencies)
+                .filter_map(|(name, reference, scope)| {
+                    Some(((workspace, dependency_key(name, reference)?), scope))
+                })
+                .collect::<Vec<_>>()
+        });
+
+        // Packages resolved with different peers are reported once
+        let mut package_scopes = HashMap::new();
+        for ((workspace, key), scope) in scopes {
+            let package_scope = package_scopes
+                .entry((workspace, strip_peers(key)))
+                .or_insert(scope);
+            *package_scope = (*package_scope).min(scope);
+        }
+        package_scopes
+    }
+
+    /// The name and version of a package from its key.
+    fn parse_key<'a>(&self, key: &'a str) -> (&'a str, Option<&'a str>) {
+        let key = key.trim_start_matches('/');
+        if self.major_version() <= 5 {
+            match key.rsplit_once('/') {
+                Some((name, version)) => (name, version.split('_').next()),
+                None => (key, None),
+            }
+        } else {
+            package_name(strip_peers(key))
+        }
+    }
+}
+
+impl DependencySpecs for PnpmLock {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        let mut workspaces: HashMap<&str, Vec<(Option<&str>, Scope)>> = HashMap::new();
+        for ((workspace, key), scope) in self.package_scopes() {
+            workspaces.entry(key).or_default().push((workspace, scope));
+        }
+        for scopes in workspaces.values_mut() {
+            scopes.sort();
+        }
+        let specs: Vec<_> = self
+            .packages
+            .iter()
+            .filter_map(|(key, package)| {
+                let resolution = &package.resolution;
+                // Packages on disk
+                if key.starts_with("file:") || resolution.directory.is_some() {
+                    return None;
+                }
+                let (key_name, key_version) = self.parse_key(key);
+                let name = package.name.as_deref().unwrap_or(key_name);
+                let version = package
+                    .version
+                    .as_deref()
+                    .or_else(|| key_version.filter(|version| !version.contains(':')));
+
+                let mut spec = DependencySpec::new(name).with_source(resolution.source());
+                if let Some(version) = version {
+                    spec = spec.with_version(version);
+                }
+                Some((strip_peers(key), spec))
+            })
+            // Reported once for each workspace requiring the package
+            .flat_map(|(key, spec)| {
+                let scopes = match workspaces.get(key) {
+                    Some(scopes) => scopes,
+                    None => return vec![spec],
+                };
+                scopes
+                    .iter()
+                    .map(|(workspace, scope)| {
+                        let spec = spec.clone().with_scope(*scope);
+                        match workspace {
+                            Some(workspace) => spec.with_workspace(workspace),
+                            None => spec,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Box::new(specs.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V5: &str = r#"
+lockfileVersion: 5.4
+specifiers:
+  '@babel/core': ^7.0.0
+  react-dom: ^18.0.0
+dependencies:
+  react-dom: 18.2.0_react@18.2.0
+  local: link:../local
+devDependencies:
+  '@babel/core': 7.22.0
+packages:
+  /@babel/core/7.22.0:
+    resolution: {integrity: sha512-abc}
+    dependencies:
+      '@babel/types': 7.22.0
+  /@babel/types/7.22.0:
+    resolution: {integrity: sha512-def}
+  /react-dom/18.2.0_react@18.2.0:
+    resolution: {integrity: sha512-ghi}
+    dependencies:
+      react: 18.2.0
+  /react/18.2.0:
+    resolution: {integrity: sha512-jkl}
+  file:../tarball.tgz:
+    resolution: {tarball: file:../tarball.tgz}
+    name: tarball
+    version: 1.0.0
+"#;
+
+    const V6: &str = r#"
+lockfileVersion: '6.0'
+importers:
+  .:
+    dependencies:
+      react-dom:
+        specifier: ^18.0.0
+        version: 18.2.0(react@18.2.0)
+    optionalDependencies:
+      '@scope/native':
+        specifier: ^1.0.0
+        version: 1.0.0
+  packages/app:
+    dependencies:
+      shared:
+        specifier: workspace:*
+        version: link:../shared
+packages:
+  /@scope/native@1.0.0:
+    resolution: {integrity: sha512-abc}
+  /react-dom@18.2.0(react@18.2.0):
+    resolution: {integrity: sha512-ghi}
+    dependencies:
+      react: 18.2.0
+  /react@18.2.0:
+    resolution: {integrity: sha512-jkl}
+  github.com/u/r/abc:
+    resolution: {tarball: https://codeload.github.com/u/r/tar.gz/abc}
+    name: gitdep
+    version: 1.0.0
+"#;
+
+    const V9: &str = r#"
+lockfileVersion: '9.0'
+importers:
+  .:
+    dependencies:
+      react-dom:
+        specifier: ^18.0.0
+        version: 18.2.0(react@18.2.0)
+    devDependencies:
+      '@types/react':
+        specifier: ^18.0.0
+        version: 18.2.0
+packages:
+  '@types/react@18.2.0':
+    resolution: {integrity: sha512-abc}
+  react-dom@18.2.0:
+    resolution: {integrity: sha512-ghi}
+    peerDependencies:
+      react: ^18.2.0
+  react@18.2.0:
+    resolution: {integrity: sha512-jkl}
+snapshots:
+  '@types/react@18.2.0': {}
+  react-dom@18.2.0(react@18.2.0):
+    dependencies:
+      react: 18.2.0
+  react@18.2.0: {}
+"#;
+
+    fn lockfile(yaml: &str) -> PnpmLock {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn specs(pnpm_lock: &PnpmLock) -> Vec<(String, Option<String>, Scope)> {
+        pnpm_lock
+            .dependency_specs()
+            .map(|spec| (spec.name, spec.version, spec.scope))
+            .collect()
+    }
+
+    fn spec(name: &str, version: &str, scope: Scope) -> (String, Option<String>, Scope) {
+        (name.to_owned(), Some(version.to_owned()), scope)
+    }
+
+    #[test]
+    fn strips_peers() {
+        assert_eq!(
+            strip_peers("/react-dom@18.2.0(react@18.2.0)"),
+            "/react-dom@18.2.0"
+        );
+        assert_eq!(strip_peers("a@1.0.0(b@2.0.0)(c@3.0.0(d@4.0.0))"), "a@1.0.0");
+        assert_eq!(strip_peers("react@18.2.0"), "react@18.2.0");
+    }
+
+    #[test]
+    fn parses_keys() {
+        let v5 = lockfile(V5);
+        assert_eq!(v5.major_version(), 5);
+        assert_eq!(v5.parse_key("/react/18.2.0"), ("react", Some("18.2.0")));
+        assert_eq!(
+            v5.parse_key("/react-dom/18.2.0_react@18.2.0"),
+            ("react-dom", Some("18.2.0"))
+        );
+        assert_eq!(
+            v5.parse_key("/@babel/core/7.22.0"),
+            ("@babel/core", Some("7.22.0"))
+        );
+
+        let v6 = lockfile(V6);
+        assert_eq!(v6.major_version(), 6);
+        assert_eq!(
+            v6.parse_key("/react-dom@18.2.0(react@18.2.0)"),
+            ("react-dom", Some("18.2.0"))
+        );
+        assert_eq!(
+            v6.parse_key("/@scope/native@1.0.0"),
+            ("@scope/native", Some("1.0.0"))
+        );
+
+        let v9 = lockfile(V9);
+        assert_eq!(v9.major_version(), 9);
+        assert_eq!(v9.parse_key("react@18.2.0"), ("react", Some("18.2.0")));
+        assert_eq!(
+            v9.parse_key("@types/react@18.2.0"),
+            ("@types/react", Some("18.2.0"))
+        );
+    }
+
+    #[test]
+    fn finds_dependency_keys() {
+        let v5 = lockfile(V5);
+        let snapshots = v5.snapshots();
+        assert_eq!(
+            v5.dependency_key(&snapshots, "react-dom", "18.2.0_react@18.2.0"),
+            Some("/react-dom/18.2.0_react@18.2.0")
+        );
+        assert_eq!(
+            v5.dependency_key(&snapshots, "@babel/core", "7.22.0"),
+            Some("/@babel/core/7.22.0")
+        );
+        assert_eq!(
+            v5.dependency_key(&snapshots, "local", "link:../local"),
+            None
+        );
+
+        let v6 = lockfile(V6);
+        let snapshots = v6.snapshots();
+        assert_eq!(
+            v6.dependency_key(&snapshots, "react-dom", "18.2.0(react@18.2.0)"),
+            Some("/react-dom@18.2.0(react@18.2.0)")
+        );
+        // Non-registry dependencies reference the key directly
+        assert_eq!(
+            v6.dependency_key(&snapshots, "gitdep", "github.com/u/r/abc"),
+            Some("github.com/u/r/abc")
+        );
+
+        let v9 = lockfile(V9);
+        let snapshots = v9.snapshots();
+        assert_eq!(
+            v9.dependency_key(&snapshots, "react-dom", "18.2.0(react@18.2.0)"),
+            Some("react-dom@18.2.0(react@18.2.0)")
+        );
+        assert_eq!(v9.dependency_key(&snapshots, "react", "19.0.0"), None);
+    }
+
+    #[test]
+    fn reads_v5_lockfiles() {
+        assert_eq!(
+            specs(&lockfile(V5)),
+            [
+                spec("@babel/core", "7.22.0", Scope::Dev),
+                spec("@babel/types", "7.22.0", Scope::Dev),
+                spec("react-dom", "18.2.0", Scope::Normal),
+                spec("react", "18.2.0", Scope::Normal),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_v6_lockfiles() {
+        let pnpm_lock = lockfile(V6);
+        assert_eq!(
+            specs(&pnpm_lock),
+            [
+                spec("@scope/native", "1.0.0", Scope::Optional),
+                spec("react-dom", "18.2.0", Scope::Normal),
+                spec("react", "18.2.0", Scope::Normal),
+                spec("gitdep", "1.0.0", Scope::Normal),
+            ]
+        );
+        let gitdep = pnpm_lock.dependency_specs().last().unwrap();
+        assert_eq!(
+            gitdep.source,
+            Source::Git {
+                url: "https://github.com/u/r".to_owned(),
+                reference: Some("abc".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_v9_lockfiles() {
+        assert_eq!(
+            specs(&lockfile(V9)),
+            [
+                spec("@types/react", "18.2.0", Scope::Dev),
+                spec("react-dom", "18.2.0", Scope::Normal),
+                spec("react", "18.2.0", Scope::Normal),
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_packages_to_importers() {
+        let pnpm_lock = lockfile(
+            r#"
+lockfileVersion: '6.0'
+importers:
+  .:
+    dependencies:
+      '@scope/a':
+        specifier: ^1
+        version: 1.0.0(react@18.2.0)
+  packages/b:
+    devDependencies:
+      '@scope/a':
+        specifier: ^1
+        version: 1.0.0(react@18.2.0)
+      b-only:
+        specifier: ^2
+        version: 2.0.0
+packages:
+  /@scope/a@1.0.0(react@18.2.0):
+    resolution: {integrity: sha512-abc}
+    dependencies:
+      react: 18.2.0
+  /react@18.2.0:
+    resolution: {integrity: sha512-def}
+  /b-only@2.0.0:
+    resolution: {integrity: sha512-ghi}
+"#,
+        );
+        let workspaces: Vec<_> = pnpm_lock
+            .dependency_specs()
+            .map(|spec| (spec.name, spec.workspace, spec.scope))
+            .collect();
+        let workspace = |name: &str, workspace: Option<&str>, scope| {
+            (name.to_owned(), workspace.map(ToOwned::to_owned), scope)
+        };
+        assert_eq!(
+            workspaces,
+            [
+                workspace("@scope/a", None, Scope::Normal),
+                workspace("@scope/a", Some("packages/b"), Scope::Dev),
+                workspace("b-only", Some("packages/b"), Scope::Dev),
+                workspace("react", None, Scope::Normal),
+                workspace("react", Some("packages/b"), Scope::Dev),
+            ]
+        );
+    }
+}
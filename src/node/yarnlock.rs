@@ -0,0 +1,588 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use log::warn;
+use percent_encoding::percent_decode_str;
+use serde_derive::Deserialize;
+
+use crate::core::{load_file, walk_scopes, DependencySpec, DependencySpecs, Scope, Source};
+use crate::node::packagejson::PackageJson;
+use crate::node::{package_name, resolved_source};
+
+#[derive(Deserialize, Default)]
+pub struct DependencyMeta {
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// A locked package, under every descriptor that resolved to it.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Package {
+    #[serde(skip)]
+    pub descriptors: Vec<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// The `<name>@<protocol>:<reference>` locator of the package, in Berry lockfiles.
+    #[serde(default)]
+    pub resolution: Option<String>,
+    /// Ranges of the packages this one requires, by name.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    /// Only written by classic, as Berry marks these in `dependenciesMeta` instead.
+    #[serde(default)]
+    pub optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub dependencies_meta: BTreeMap<String, DependencyMeta>,
+    /// The scope in each workspace requiring this package, found by walking the locked graph
+    /// from the manifest beside the lockfile.
+    #[serde(skip)]
+    pub scopes: Vec<(Option<String>, Scope)>,
+}
+
+/// Yarn Berry's lockfile, which is YAML.
+#[derive(Deserialize)]
+struct BerryLock {
+    #[serde(default, rename = "__metadata")]
+    _metadata: serde_yaml::Value,
+    /// Packages keyed by their descriptors, separated by `, `.
+    #[serde(flatten)]
+    packages: BTreeMap<String, Package>,
+}
+
+/// Yarn's lockfile, in either the classic (v1) or Berry (v2 and later) format.
+pub struct YarnLock {
+    pub packages: Vec<Package>,
+}
+
+impl YarnLock {
+    /// Load the lockfile, finding scopes from the `package.json` beside it.
+    pub fn load(path: &Path) -> Result<Self> {
+        let buffer = load_file(path)?;
+        let mut yarn_lock =
+            Self::parse(&buffer).with_context(|| format!("With path {}", path.display()))?;
+
+        let manifest_path = path.with_file_name("package.json");
+        if manifest_path.is_file() {
+            yarn_lock.find_scopes(&PackageJson::load(&manifest_path)?);
+        } else {
+            warn!(
+                "No package.json beside {}, so every package is reported as normal",
+                path.display()
+            );
+        }
+        Ok(yarn_lock)
+    }
+
+    fn parse(buffer: &str) -> Result<Self> {
+        // Berry writes a YAML metadata section, which classic lockfiles don't have
+        if buffer.lines().any(|line| line.starts_with("__metadata:")) {
+            let berry_lock: BerryLock = serde_yaml::from_str(buffer)?;
+            let packages = berry_lock
+                .packages
+                .into_iter()
+                .map(|(descriptors, mut package)| {
+                    package.descriptors = split_descriptors(&descriptors);
+                    package
+                })
+                .collect();
+            Ok(Self { packages })
+        } else {
+            Ok(Self::parse_classic(buffer))
+        }
+    }
+
+    /// Read the classic format, which is YAML-like but not YAML.
+    fn parse_classic(buffer: &str) -> Self {
+        let mut packages = Vec::new();
+        let mut package: Option<Package> = None;
+        let mut section: Option<&str> = None;
+        for line in buffer.lines() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if !line.starts_with(' ') {
+                packages.extend(package.take());
+                package = Some(Package {
+                    descriptors: split_descriptors(line.trim_end().trim_end_matches(':')),
+                    ..Default::default()
+                });
+                section = None;
+                continue;
+            }
+            let package = match &mut package {
+                Some(package) => package,
+                None => continue,
+            };
+            // Dependency lists are indented beneath their field
+            if let Some(entry) = line.strip_prefix("    ") {
+                let dependencies = match section {
+                    Some("dependencies") => &mut package.dependencies,
+                    Some("optionalDependencies") => &mut package.optional_dependencies,
+                    _ => continue,
+                };
+                if let Some((name, range)) = entry.trim().split_once(' ') {
+                    dependencies.insert(unquote(name).to_owned(), unquote(range).to_owned());
+                }
+                continue;
+            }
+            let field = match line.strip_prefix("  ") {
+                Some(field) if !field.starts_with(' ') => field.trim_end(),
+                _ => continue,
+            };
+            section = field.strip_suffix(':');
+            if let Some(("version", version)) = field.split_once(' ') {
+                package.version = Some(unquote(version).to_owned());
+            }
+        }
+        packages.extend(package);
+        Self { packages }
+    }
+
+    /// Walk the locked graph from the manifest and its workspace members, setting the scope
+    /// of each package in the workspaces requiring it.
+    fn find_scopes(&mut self, package_json: &PackageJson) {
+        let mut indices: HashMap<&str, usize> = HashMap::new();
+        for (index, package) in self.packages.iter().enumerate() {
+            for descriptor in &package.descriptors {
+                indices.insert(descriptor, index);
+            }
+        }
+        // Berry adds the default `npm:` protocol to ranges from the manifest
+        let lookup = |name: &str, range: &str| {
+            indices
+                .get(format!("{}@{}", name, range).as_str())
+                .or_else(|| indices.get(format!("{}@npm:{}", name, range).as_str()))
+                .copied()
+        };
+
+        let specs: Vec<_> = package_json.dependency_specs().collect();
+        let roots = specs.iter().filter_map(|spec| {
+            let index = lookup(&spec.name, spec.requirement.as_deref()?)?;
+            Some(((spec.workspace.as_deref(), index), spec.scope))
+        });
+        let scopes = walk_scopes(roots, |(workspace, index), scope| {
+            let package = &self.packages[index];
+            let dependencies = package.dependencies.iter().map(|(name, range)| {
+                let optional = package
+                    .dependencies_meta
+                    .get(name)
+                    .map(|meta| meta.optional)
+                    .unwrap_or(false);
+                let scope = if optional { scope.optional() } else { scope };
+                (name, range, scope)
+            });
+            let optional_dependencies = package
+                .optional_dependencies
+                .iter()
+                .map(|(name, range)| (name, range, scope.optional()));
+            dependencies
+                .chain(optional_dependencies)
+                .filter_map(|(name, range, scope)| Some(((workspace, lookup(name, range)?), scope)))
+                .collect::<Vec<_>>()
+        });
+
+        for ((workspace, index), scope) in scopes {
+            let workspace = workspace.map(ToOwned::to_owned);
+            self.packages[index].scopes.push((workspace, scope));
+        }
+        for package in &mut self.packages {
+            package.scopes.sort();
+        }
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches('"')
+}
+
+fn split_descriptors(descriptors: &str) -> Vec<String> {
+    descriptors
+        .split(',')
+        .map(|descriptor| unquote(descriptor).to_owned())
+        .filter(|descriptor| !descriptor.is_empty())
+        .collect()
+}
+
+/// A git source, with the reference from a URL fragment such as `#commit=<sha>` in Berry or
+/// `#<sha>` in classic.
+fn git_source(url: &str) -> Source {
+    let (url, reference) = match url.split_once('#') {
+        Some((url, fragment)) => {
+            let reference = fragment
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("commit="))
+                .unwrap_or(fragment);
+            (url, Some(reference.to_owned()))
+        }
+        None => (url, None),
+    };
+    let url = match url.strip_prefix("github:") {
+        Some(repository) => format!("https://github.com/{}", repository),
+        None => url.trim_start_matches("git+").to_owned(),
+    };
+    Source::Git { url, reference }
+}
+
+/// The spec for a Berry locator, or `None` if the package is local.
+fn berry_spec(locator: &str, version: Option<&str>) -> Option<DependencySpec> {
+    let (name, reference) = package_name(locator);
+    let spec = DependencySpec::new(name);
+    let (protocol, value) = match reference.and_then(|reference| reference.split_once(':')) {
+        Some(parts) => parts,
+        None => return Some(with_version(spec, version)),
+    };
+    match protocol {
+        "npm" => Some(spec.with_version(value)),
+        // Workspace members and packages on disk
+        "workspace" | "portal" | "link" | "file" => None,
+        // Patched packages are reported as the package they patch
+        "patch" => {
+            let (patched, _patch) = value.split_once('#').unwrap_or((value, ""));
+            let patched = percent_decode_str(patched).decode_utf8_lossy();
+            berry_spec(&patched, version)
+        }
+        _ if reference.map(|reference| reference.contains("#commit=")) == Some(true)
+            || protocol.starts_with("git") =>
+        {
+            let source = git_source(reference.unwrap_or_default());
+            Some(with_version(spec.with_source(source), version))
+        }
+        // Tarballs downloaded from a URL
+        _ => Some(with_version(spec, version)),
+    }
+}
+
+/// The spec for a classic descriptor, or `None` if the package is local.
+fn classic_spec(descriptor: &str, version: Option<&str>) -> Option<DependencySpec> {
+    let (local_name, range) = package_name(descriptor);
+    let range = range.unwrap_or_default();
+    // Aliases are written as `<alias>@npm:<name>@<range>`
+    if let Some(aliased) = range.strip_prefix("npm:") {
+        let (name, _range) = package_name(aliased);
+        return Some(with_version(
+            DependencySpec::new(name).with_alias(local_name),
+            version,
+        ));
+    }
+    let spec = DependencySpec::new(local_name);
+    match resolved_source(range)? {
+        Source::Default => Some(with_version(spec, version)),
+        source => Some(with_version(spec.with_source(source), version)),
+    }
+}
+
+fn with_version(spec: DependencySpec, version: Option<&str>) -> DependencySpec {
+    match version {
+        Some(version) => spec.with_version(version),
+        None => spec,
+    }
+}
+
+impl Package {
+    fn spec(&self) -> Option<DependencySpec> {
+        let descriptor = self.descriptors.first()?;
+        let version = self.version.as_deref();
+        let spec = match &self.resolution {
+            Some(resolution) => {
+                let spec = berry_spec(resolution, version)?;
+                // Berry resolves aliases to the published name
+                let (local_name, _range) = package_name(descriptor);
+                if local_name != spec.name {
+                    Some(spec.with_alias(local_name))
+                } else {
+                    Some(spec)
+                }
+            }
+            None => classic_spec(descriptor, version),
+        }?;
+        Some(spec)
+    }
+
+    /// The spec for each workspace requiring this package, or a single one if not known.
+    fn specs(&self) -> Vec<DependencySpec> {
+        let spec = match self.spec() {
+            Some(spec) => spec,
+            None => return Vec::new(),
+        };
+        if self.scopes.is_empty() {
+            return vec![spec];
+        }
+        self.scopes
+            .iter()
+            .map(|(workspace, scope)| {
+                let spec = spec.clone().with_scope(*scope);
+                match workspace {
+                    Some(workspace) => spec.with_workspace(workspace),
+                    None => spec,
+                }
+            })
+            .collect()
+    }
+}
+
+impl DependencySpecs for YarnLock {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(self.packages.iter().flat_map(Package::specs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLASSIC: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+"@babel/code-frame@^7.0.0", "@babel/code-frame@^7.10.4":
+  version "7.12.13"
+  resolved "https://registry.yarnpkg.com/@babel/code-frame/-/code-frame-7.12.13.tgz#dcfc826b"
+  integrity sha512-abc
+  dependencies:
+    "@babel/highlight" "^7.12.13"
+  optionalDependencies:
+    fsevents "~2.3.1"
+
+"@babel/highlight@^7.12.13":
+  version "7.13.10"
+  resolved "https://registry.yarnpkg.com/@babel/highlight/-/highlight-7.13.10.tgz#a8b2a66f"
+  integrity sha512-def
+
+fsevents@~2.3.1:
+  version "2.3.2"
+  resolved "https://registry.yarnpkg.com/fsevents/-/fsevents-2.3.2.tgz#8a526f78"
+
+jest@^27.0.0:
+  version "27.5.1"
+
+my-alias@npm:lodash@^4.17.0:
+  version "4.17.21"
+  resolved "https://registry.yarnpkg.com/lodash/-/lodash-4.17.21.tgz"
+
+gitdep@github:u/r#abc:
+  version "1.0.0"
+  resolved "https://codeload.github.com/u/r/tar.gz/abc"
+
+local@file:../local:
+  version "0.0.1"
+"#;
+
+    const BERRY: &str = r#"# This file is generated by running "yarn install" inside your project.
+
+__metadata:
+  version: 6
+  cacheKey: 8
+
+"@babel/code-frame@npm:^7.0.0, @babel/code-frame@npm:^7.10.4":
+  version: 7.12.13
+  resolution: "@babel/code-frame@npm:7.12.13"
+  dependencies:
+    "@babel/highlight": ^7.12.13
+    fsevents: ~2.3.1
+  dependenciesMeta:
+    fsevents:
+      optional: true
+  checksum: 471532bb
+  languageName: node
+  linkType: hard
+
+"@babel/highlight@npm:^7.12.13":
+  version: 7.13.10
+  resolution: "@babel/highlight@npm:7.13.10"
+
+"fsevents@npm:~2.3.1":
+  version: 2.3.2
+  resolution: "fsevents@npm:2.3.2"
+
+"app@workspace:.":
+  version: 0.0.0-use.local
+  resolution: "app@workspace:."
+  languageName: unknown
+  linkType: soft
+
+"my-alias@npm:lodash@^4":
+  version: 4.17.21
+  resolution: "lodash@npm:4.17.21"
+
+"resolve@patch:resolve@^1.20.0#~builtin<compat/resolve>":
+  version: 1.22.1
+  resolution: "resolve@patch:resolve@npm%3A1.22.1#~builtin<compat/resolve>::version=1.22.1&hash=c3c19d"
+
+"gitdep@github:u/r#commit=abc":
+  version: 1.0.0
+  resolution: "gitdep@github:u/r#commit=abc"
+
+"p@portal:../p::locator=app%40workspace%3A.":
+  version: 0.0.0-use.local
+  resolution: "p@portal:../p::locator=app%40workspace%3A."
+"#;
+
+    fn specs(yarn_lock: &YarnLock) -> Vec<DependencySpec> {
+        yarn_lock.dependency_specs().collect()
+    }
+
+    fn package_json(json: &str) -> PackageJson {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn parses_classic_entries() {
+        let yarn_lock = YarnLock::parse(CLASSIC).unwrap();
+        let package = &yarn_lock.packages[0];
+        assert_eq!(
+            package.descriptors,
+            ["@babel/code-frame@^7.0.0", "@babel/code-frame@^7.10.4"]
+        );
+        assert_eq!(package.version.as_deref(), Some("7.12.13"));
+        assert_eq!(package.resolution, None);
+        assert_eq!(
+            package
+                .dependencies
+                .get("@babel/highlight")
+                .map(String::as_str),
+            Some("^7.12.13")
+        );
+        assert_eq!(
+            package
+                .optional_dependencies
+                .get("fsevents")
+                .map(String::as_str),
+            Some("~2.3.1")
+        );
+        assert_eq!(
+            yarn_lock.packages[1].descriptors,
+            ["@babel/highlight@^7.12.13"]
+        );
+        assert!(yarn_lock.packages[1].dependencies.is_empty());
+    }
+
+    #[test]
+    fn reads_classic_specs() {
+        let specs = specs(&YarnLock::parse(CLASSIC).unwrap());
+        // The `file:` package is left out
+        assert_eq!(specs.len(), 6);
+        assert_eq!(specs[0].name, "@babel/code-frame");
+        assert_eq!(specs[0].version.as_deref(), Some("7.12.13"));
+        assert_eq!(specs[0].source, Source::Default);
+
+        assert_eq!(specs[4].name, "lodash");
+        assert_eq!(specs[4].alias.as_deref(), Some("my-alias"));
+        assert_eq!(specs[4].version.as_deref(), Some("4.17.21"));
+
+        assert_eq!(specs[5].name, "gitdep");
+        assert_eq!(
+            specs[5].source,
+            Source::Git {
+                url: "https://github.com/u/r".to_owned(),
+                reference: Some("abc".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_berry_locators() {
+        let specs = specs(&YarnLock::parse(BERRY).unwrap());
+        let names: Vec<_> = specs.iter().map(|spec| spec.name.as_str()).collect();
+        // Workspace and portal packages are left out
+        assert_eq!(
+            names,
+            [
+                "@babel/code-frame",
+                "@babel/highlight",
+                "fsevents",
+                "gitdep",
+                "lodash",
+                "resolve",
+            ]
+        );
+
+        assert_eq!(specs[0].version.as_deref(), Some("7.12.13"));
+        assert_eq!(specs[4].alias.as_deref(), Some("my-alias"));
+        assert_eq!(specs[4].version.as_deref(), Some("4.17.21"));
+        // Patched packages are reported as the package they patch
+        assert_eq!(specs[5].alias, None);
+        assert_eq!(specs[5].version.as_deref(), Some("1.22.1"));
+        assert_eq!(specs[5].source, Source::Default);
+        assert_eq!(
+            specs[3].source,
+            Source::Git {
+                url: "https://github.com/u/r".to_owned(),
+                reference: Some("abc".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn finds_scopes_from_the_manifest() {
+        let manifest = package_json(
+            r#"{
+                "dependencies": {"@babel/code-frame": "^7.0.0"},
+                "devDependencies": {"jest": "^27.0.0", "@babel/highlight": "^7.12.13"}
+            }"#,
+        );
+        let mut yarn_lock = YarnLock::parse(CLASSIC).unwrap();
+        yarn_lock.find_scopes(&manifest);
+        let scopes: Vec<_> = specs(&yarn_lock)
+            .into_iter()
+            .map(|spec| (spec.name, spec.scope))
+            .collect();
+        assert_eq!(
+            scopes[..4],
+            [
+                ("@babel/code-frame".to_owned(), Scope::Normal),
+                // Normal through another package takes precedence over dev
+                ("@babel/highlight".to_owned(), Scope::Normal),
+                ("fsevents".to_owned(), Scope::Optional),
+                ("jest".to_owned(), Scope::Dev),
+            ]
+        );
+
+        let manifest = package_json(r#"{"devDependencies": {"@babel/code-frame": "^7.10.4"}}"#);
+        let mut yarn_lock = YarnLock::parse(BERRY).unwrap();
+        yarn_lock.find_scopes(&manifest);
+        let scopes: Vec<_> = specs(&yarn_lock)
+            .into_iter()
+            .map(|spec| (spec.name, spec.scope))
+            .collect();
+        assert_eq!(
+            scopes[..3],
+            [
+                ("@babel/code-frame".to_owned(), Scope::Dev),
+                ("@babel/highlight".to_owned(), Scope::Dev),
+                ("fsevents".to_owned(), Scope::Dev),
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_packages_to_workspaces() {
+        let mut manifest = package_json(r#"{"devDependencies": {"jest": "^27.0.0"}}"#);
+        manifest.members = vec![
+            package_json(r#"{"dependencies": {"@babel/code-frame": "^7.0.0"}}"#),
+            package_json(r#"{"devDependencies": {"@babel/highlight": "^7.12.13"}}"#),
+        ];
+        manifest.members[0].workspace = Some("a".to_owned());
+        manifest.members[1].workspace = Some("b".to_owned());
+        let mut yarn_lock = YarnLock::parse(CLASSIC).unwrap();
+        yarn_lock.find_scopes(&manifest);
+        let workspaces: Vec<_> = specs(&yarn_lock)
+            .into_iter()
+            .map(|spec| (spec.name, spec.workspace, spec.scope))
+            .collect();
+        let workspace = |name: &str, workspace: Option<&str>, scope| {
+            (name.to_owned(), workspace.map(ToOwned::to_owned), scope)
+        };
+        assert_eq!(
+            workspaces[..5],
+            [
+                workspace("@babel/code-frame", Some("a"), Scope::Normal),
+                workspace("@babel/highlight", Some("a"), Scope::Normal),
+                workspace("@babel/highlight", Some("b"), Scope::Dev),
+                workspace("fsevents", Some("a"), Scope::Optional),
+                workspace("jest", None, Scope::Dev),
+            ]
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+
+use crate::python::metadata::CoreMetadata;
+
+/// License files bundled with older wheels, which don't declare them in the metadata.
+const LICENSE_FILE_PREFIXES: [&str; 4] = ["LICENSE", "LICENCE", "COPYING", "NOTICE"];
+
+/// Core metadata and license files read from a wheel or sdist.
+pub struct Distribution {
+    pub metadata: CoreMetadata,
+    /// Contents of each bundled license file, by its path in the project.
+    pub license_files: Vec<(String, Vec<u8>)>,
+}
+
+/// Metadata and license files are small, so larger files such as compiled modules are skipped.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Read the small files in an archive.
+fn read_archive(filename: &str, bytes: &[u8]) -> Result<Option<HashMap<String, Vec<u8>>>> {
+    let mut files = HashMap::new();
+    if filename.ends_with(".whl") || filename.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes))
+            .with_context(|| format!("Reading archive '{}' failed.", filename))?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            if file.is_dir() || file.size() > MAX_FILE_SIZE {
+                continue;
+            }
+            let mut buffer = Vec::new();
+            file.read_to_end(&mut buffer)?;
+            files.insert(file.name().to_owned(), buffer);
+        }
+    } else if filename.ends_with(".tar.gz") {
+        let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+        for file in archive
+            .entries()
+            .with_context(|| format!("Reading archive '{}' failed.", filename))?
+        {
+            let mut file = file?;
+            if file.size() > MAX_FILE_SIZE {
+                continue;
+            }
+            let path = file.path()?.to_string_lossy().into_owned();
+            let mut buffer = Vec::new();
+            file.read_to_end(&mut buffer)?;
+            files.insert(path, buffer);
+        }
+    } else {
+        return Ok(None);
+    }
+    Ok(Some(files))
+}
+
+/// The top level directory of a path in an archive, and the path within it.
+fn split_top(path: &str) -> Option<(&str, &str)> {
+    path.split_once('/')
+}
+
+impl Distribution {
+    /// Read the `METADATA` of a wheel or the `PKG-INFO` of an sdist, with the license files
+    /// it declares in `License-File`.
+    pub fn read(filename: &str, bytes: &[u8]) -> Result<Option<Self>> {
+        let mut files = match read_archive(filename, bytes)? {
+            Some(files) => files,
+            None => return Ok(None),
+        };
+
+        // Metadata is in `<name>-<version>.dist-info/` for wheels, and `<name>-<version>/` for sdists
+        let wheel = filename.ends_with(".whl");
+        let metadata_name = if wheel { "METADATA" } else { "PKG-INFO" };
+        let metadata_path = files
+            .keys()
+            .find(|path| match split_top(path) {
+                Some((top, rest)) => {
+                    rest == metadata_name && (!wheel || top.ends_with(".dist-info"))
+                }
+                None => false,
+            })
+            .cloned();
+        let metadata_path = match metadata_path {
+            Some(metadata_path) => metadata_path,
+            None => return Ok(None),
+        };
+        let directory = split_top(&metadata_path)
+            .map(|(top, _)| top.to_owned())
+            .unwrap_or_default();
+        let metadata = CoreMetadata::parse(&String::from_utf8_lossy(&files[&metadata_path]));
+
+        let mut license_files = Vec::new();
+        for license_file in &metadata.license_files {
+            // Wheels keep license files under `licenses/` since PEP 639, and at the top before
+            let candidates = if wheel {
+                vec![
+                    format!("{}/licenses/{}", directory, license_file),
+                    format!("{}/{}", directory, license_file),
+                ]
+            } else {
+                vec![format!("{}/{}", directory, license_file)]
+            };
+            if let Some(contents) = candidates.iter().find_map(|path| files.remove(path)) {
+                license_files.push((license_file.to_owned(), contents));
+            }
+        }
+        if metadata.license_files.is_empty() {
+            let mut undeclared: Vec<_> = files
+                .into_iter()
+                .filter_map(|(path, contents)| {
+                    let (top, name) = split_top(&path)?;
+                    let license = top == directory
+                        && LICENSE_FILE_PREFIXES
+                            .iter()
+                            .any(|prefix| name.to_uppercase().starts_with(prefix));
+                    if license {
+                        Some((name.to_owned(), contents))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            undeclared.sort();
+            license_files.extend(undeclared);
+        }
+
+        Ok(Some(Self {
+            metadata,
+            license_files,
+        }))
+    }
+
+    /// Write the license files into the given directory, returning their paths.
+    pub fn save_license_files(&self, directory: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for (path, contents) in &self.license_files {
+            // Keep only normal components, so files can't be written outside the directory
+            let relative: PathBuf = Path::new(path)
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect();
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+            let license_path = directory.join(relative);
+            if let Some(parent) = license_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&license_path, contents)
+                .with_context(|| format!("Writing license file {}", license_path.display()))?;
+            paths.push(license_path);
+        }
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use zip::write::FileOptions;
+
+    use super::*;
+
+    fn wheel(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, contents) in files {
+            writer.start_file(*path, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn sdist(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn reads_declared_license_files_from_wheels() {
+        let bytes = wheel(&[
+            ("foo/__init__.py", ""),
+            (
+                "foo-1.0.dist-info/METADATA",
+                "Name: foo\nLicense-Expression: MIT\nLicense-File: LICENSE\nLicense-File: docs/NOTICE\n",
+            ),
+            ("foo-1.0.dist-info/licenses/LICENSE", "MIT License"),
+            ("foo-1.0.dist-info/docs/NOTICE", "Notice"),
+            ("foo-1.0.dist-info/COPYING", "Not declared"),
+        ]);
+        let distribution = Distribution::read("foo-1.0-py3-none-any.whl", &bytes)
+            .unwrap()
+            .unwrap();
+        assert_eq!(distribution.metadata.license(), Some("MIT".to_owned()));
+        assert_eq!(
+            distribution.license_files,
+            [
+                ("LICENSE".to_owned(), b"MIT License".to_vec()),
+                ("docs/NOTICE".to_owned(), b"Notice".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_undeclared_license_files_in_sdists() {
+        let bytes = sdist(&[
+            ("foo-1.0/PKG-INFO", "Name: foo\nLicense: BSD\n"),
+            ("foo-1.0/setup.py", ""),
+            ("foo-1.0/LICENSE.txt", "BSD License"),
+            ("foo-1.0/Copying", "Copying"),
+            ("foo-1.0/foo/LICENSE", "Vendored"),
+            ("foo-1.0/foo.egg-info/PKG-INFO", "Name: other\n"),
+        ]);
+        let distribution = Distribution::read("foo-1.0.tar.gz", &bytes)
+            .unwrap()
+            .unwrap();
+        assert_eq!(distribution.metadata.name.as_deref(), Some("foo"));
+        assert_eq!(
+            distribution.license_files,
+            [
+                ("Copying".to_owned(), b"Copying".to_vec()),
+                ("LICENSE.txt".to_owned(), b"BSD License".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_other_files() {
+        assert!(Distribution::read("foo-1.0.exe", b"").unwrap().is_none());
+        let bytes = wheel(&[("foo/__init__.py", "")]);
+        assert!(Distribution::read("foo-1.0-py3-none-any.whl", &bytes)
+            .unwrap()
+            .is_none());
+        assert!(Distribution::read("foo-1.0-py3-none-any.whl", b"broken").is_err());
+    }
+
+    #[test]
+    fn saves_license_files_inside_directory() {
+        let directory =
+            std::env::temp_dir().join(format!("yalich-distribution-{}", std::process::id()));
+        let distribution = Distribution {
+            metadata: CoreMetadata::default(),
+            license_files: vec![
+                ("../../LICENSE".to_owned(), b"MIT".to_vec()),
+                ("docs/NOTICE".to_owned(), b"Notice".to_vec()),
+                ("..".to_owned(), b"Nothing".to_vec()),
+            ],
+        };
+        let paths = distribution.save_license_files(&directory).unwrap();
+        assert_eq!(
+            paths,
+            [directory.join("LICENSE"), directory.join("docs/NOTICE")]
+        );
+        assert_eq!(fs::read(&paths[0]).unwrap(), b"MIT");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,228 @@
+/// SPDX ids for trove classifiers under `License ::`.
+///
+/// Only classifiers naming exactly one license are mapped, so not `BSD License` or others
+/// which leave out the version or variant. GNU licenses without "or later" are left out too,
+/// as they are used for both the "only" and "or later" terms.
+const CLASSIFIER_LICENSES: &[(&str, &str)] = &[
+    (
+        "OSI Approved :: Apache Software License 2.0 (Apache-2.0)",
+        "Apache-2.0",
+    ),
+    (
+        "OSI Approved :: Boost Software License 1.0 (BSL-1.0)",
+        "BSL-1.0",
+    ),
+    (
+        "OSI Approved :: Eclipse Public License 1.0 (EPL-1.0)",
+        "EPL-1.0",
+    ),
+    (
+        "OSI Approved :: Eclipse Public License 2.0 (EPL-2.0)",
+        "EPL-2.0",
+    ),
+    (
+        "OSI Approved :: European Union Public Licence 1.1 (EUPL 1.1)",
+        "EUPL-1.1",
+    ),
+    (
+        "OSI Approved :: European Union Public Licence 1.2 (EUPL 1.2)",
+        "EUPL-1.2",
+    ),
+    (
+        "OSI Approved :: GNU Affero General Public License v3 or later (AGPLv3+)",
+        "AGPL-3.0-or-later",
+    ),
+    (
+        "OSI Approved :: GNU General Public License v2 or later (GPLv2+)",
+        "GPL-2.0-or-later",
+    ),
+    (
+        "OSI Approved :: GNU General Public License v3 or later (GPLv3+)",
+        "GPL-3.0-or-later",
+    ),
+    (
+        "OSI Approved :: GNU Lesser General Public License v2 or later (LGPLv2+)",
+        "LGPL-2.0-or-later",
+    ),
+    (
+        "OSI Approved :: GNU Lesser General Public License v3 or later (LGPLv3+)",
+        "LGPL-3.0-or-later",
+    ),
+    (
+        "OSI Approved :: Historical Permission Notice and Disclaimer (HPND)",
+        "HPND",
+    ),
+    ("OSI Approved :: ISC License (ISCL)", "ISC"),
+    ("OSI Approved :: MIT License", "MIT"),
+    (
+        "OSI Approved :: MIT No Attribution License (MIT-0)",
+        "MIT-0",
+    ),
+    (
+        "OSI Approved :: Mozilla Public License 1.1 (MPL 1.1)",
+        "MPL-1.1",
+    ),
+    (
+        "OSI Approved :: Mozilla Public License 2.0 (MPL 2.0)",
+        "MPL-2.0",
+    ),
+    (
+        "OSI Approved :: Python Software Foundation License",
+        "PSF-2.0",
+    ),
+    ("OSI Approved :: The Unlicense (Unlicense)", "Unlicense"),
+    (
+        "OSI Approved :: Universal Permissive License (UPL)",
+        "UPL-1.0",
+    ),
+    ("OSI Approved :: zlib/libpng License", "Zlib"),
+    (
+        "CC0 1.0 Universal (CC0 1.0) Public Domain Dedication",
+        "CC0-1.0",
+    ),
+];
+
+/// Free-text licenses longer than this are taken to be the full license text.
+const MAX_LICENSE_NAME_LENGTH: usize = 100;
+
+/// License classifiers, as SPDX ids where mapped and otherwise by their last part, along with
+/// whether every classifier was mapped.
+fn classifier_licenses<'a>(classifiers: impl IntoIterator<Item = &'a str>) -> (Vec<String>, bool) {
+    let mut licenses: Vec<String> = Vec::new();
+    let mut exact = true;
+    for classifier in classifiers {
+        let classifier = match classifier.strip_prefix("License :: ") {
+            Some(classifier) => classifier.trim(),
+            None => continue,
+        };
+        let license = match CLASSIFIER_LICENSES
+            .iter()
+            .find(|(other, _)| *other == classifier)
+        {
+            Some((_, license)) => (*license).to_owned(),
+            // A bare `OSI Approved` names no license at all
+            None if classifier == "OSI Approved" => continue,
+            None => {
+                exact = false;
+                classifier
+                    .rsplit(" :: ")
+                    .next()
+                    .unwrap_or(classifier)
+                    .to_owned()
+            }
+        };
+        if !licenses.contains(&license) {
+            licenses.push(license);
+        }
+    }
+    (licenses, exact)
+}
+
+/// The free-text license, unless it is empty, a placeholder, or the full license text.
+fn text_license(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty()
+        || text.eq_ignore_ascii_case("UNKNOWN")
+        || text.contains('\n')
+        || text.len() > MAX_LICENSE_NAME_LENGTH
+    {
+        return None;
+    }
+    Some(text.to_owned())
+}
+
+/// Choose a license by preferring the PEP 639 `License-Expression`, then license classifiers
+/// naming exactly one license, and then the free-text `License` field.
+///
+/// Failing those, any other license classifiers are reported as written. Several classifiers
+/// may mean either a choice or a combination, so all of them are required.
+pub fn select_license<'a>(
+    expression: Option<&str>,
+    classifiers: impl IntoIterator<Item = &'a str>,
+    text: Option<&str>,
+) -> Option<String> {
+    if let Some(expression) = expression
+        .map(str::trim)
+        .filter(|expression| !expression.is_empty())
+    {
+        return Some(expression.to_owned());
+    }
+    let (licenses, exact) = classifier_licenses(classifiers);
+    if licenses.is_empty() {
+        return text.and_then(text_license);
+    }
+    let classifier_license = licenses.join(" AND ");
+    if exact {
+        return Some(classifier_license);
+    }
+    text.and_then(text_license).or(Some(classifier_license))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIT: &str = "License :: OSI Approved :: MIT License";
+    const APACHE: &str = "License :: OSI Approved :: Apache Software License";
+    const GPL3: &str = "License :: OSI Approved :: GNU General Public License v3 (GPLv3)";
+    const GPL3_OR_LATER: &str =
+        "License :: OSI Approved :: GNU General Public License v3 or later (GPLv3+)";
+
+    fn select(
+        expression: Option<&str>,
+        classifiers: &[&str],
+        text: Option<&str>,
+    ) -> Option<String> {
+        select_license(expression, classifiers.iter().copied(), text)
+    }
+
+    #[test]
+    fn prefers_license_expression() {
+        assert_eq!(
+            select(Some(" MIT OR Apache-2.0 "), &[GPL3_OR_LATER], Some("BSD")),
+            Some("MIT OR Apache-2.0".to_owned())
+        );
+    }
+
+    #[test]
+    fn maps_exact_classifiers() {
+        assert_eq!(
+            select(None, &[MIT, GPL3_OR_LATER], Some("Custom")),
+            Some("MIT AND GPL-3.0-or-later".to_owned())
+        );
+        assert_eq!(
+            select(None, &["License :: OSI Approved", MIT, MIT], None),
+            Some("MIT".to_owned())
+        );
+    }
+
+    #[test]
+    fn prefers_text_over_ambiguous_classifiers() {
+        assert_eq!(
+            select(None, &[APACHE], Some("Apache 2.0")),
+            Some("Apache 2.0".to_owned())
+        );
+        assert_eq!(
+            select(None, &[MIT, GPL3], Some("MIT or GPLv3")),
+            Some("MIT or GPLv3".to_owned())
+        );
+        assert_eq!(
+            select(None, &[GPL3], Some("UNKNOWN")),
+            Some("GNU General Public License v3 (GPLv3)".to_owned())
+        );
+    }
+
+    #[test]
+    fn skips_license_text() {
+        assert_eq!(select(None, &[], Some("")), None);
+        assert_eq!(
+            select(None, &[], Some("MIT License\n\nCopyright (c) 2020")),
+            None
+        );
+        assert_eq!(select(None, &[], Some(&"x".repeat(101))), None);
+        assert_eq!(
+            select(None, &[], Some(" BSD-3-Clause ")),
+            Some("BSD-3-Clause".to_owned())
+        );
+    }
+}
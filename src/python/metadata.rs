@@ -0,0 +1,133 @@
+use crate::python::license::select_license;
+use crate::python::pypi::{find_repository, select_homepage};
+
+/// Core metadata of a distribution, as in the `METADATA` file of a wheel.
+#[derive(Debug, Default)]
+pub struct CoreMetadata {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub license: Option<String>,
+    /// SPDX expression, as defined by PEP 639.
+    pub license_expression: Option<String>,
+    pub license_files: Vec<String>,
+    pub classifiers: Vec<String>,
+    pub home_page: Option<String>,
+    /// Labelled URLs, from `Project-URL: <label>, <url>` headers.
+    pub project_urls: Vec<(String, String)>,
+}
+
+impl CoreMetadata {
+    /// Parse the email-style headers, ignoring the description in the body.
+    pub fn parse(buffer: &str) -> Self {
+        let mut headers: Vec<(&str, String)> = Vec::new();
+        for line in buffer.lines() {
+            if line.is_empty() {
+                break;
+            }
+            // Long values, such as a license text, continue on indented lines
+            if line.starts_with(char::is_whitespace) {
+                if let Some((_key, value)) = headers.last_mut() {
+                    let line = line.trim_start();
+                    value.push('\n');
+                    value.push_str(line.strip_prefix('|').unwrap_or(line));
+                }
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                headers.push((key, value.trim().to_owned()));
+            }
+        }
+
+        let mut metadata = Self::default();
+        for (key, value) in headers {
+            match key.to_ascii_lowercase().as_str() {
+                "name" => metadata.name = Some(value),
+                "version" => metadata.version = Some(value),
+                "license" => metadata.license = Some(value),
+                "license-expression" => metadata.license_expression = Some(value),
+                "license-file" => metadata.license_files.push(value),
+                "classifier" => metadata.classifiers.push(value),
+                "home-page" => metadata.home_page = Some(value),
+                "project-url" => {
+                    if let Some((label, url)) = value.split_once(',') {
+                        metadata
+                            .project_urls
+                            .push((label.trim().to_owned(), url.trim().to_owned()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        metadata
+    }
+
+    pub fn license(&self) -> Option<String> {
+        select_license(
+            self.license_expression.as_deref(),
+            self.classifiers.iter().map(String::as_str),
+            self.license.as_deref(),
+        )
+    }
+
+    pub fn homepage(&self) -> Option<&str> {
+        let repository = find_repository(
+            self.project_urls
+                .iter()
+                .map(|(label, url)| (label.as_str(), url.as_str())),
+        );
+        select_homepage(self.home_page.as_deref(), repository)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_headers() {
+        let metadata = CoreMetadata::parse(
+            "Metadata-Version: 2.4
+Name: foo
+Version: 1.0
+License-Expression: MIT
+License-File: LICENSE
+License-File: NOTICE
+Classifier: License :: OSI Approved :: MIT License
+Classifier: Programming Language :: Python
+Home-page: https://foo.readthedocs.io
+Project-URL: Source, https://github.com/foo/foo
+Project-URL: broken
+
+Name: not a header
+",
+        );
+        assert_eq!(metadata.name.as_deref(), Some("foo"));
+        assert_eq!(metadata.version.as_deref(), Some("1.0"));
+        assert_eq!(metadata.license_files, ["LICENSE", "NOTICE"]);
+        assert_eq!(metadata.classifiers.len(), 2);
+        assert_eq!(
+            metadata.project_urls,
+            [("Source".to_owned(), "https://github.com/foo/foo".to_owned())]
+        );
+        assert_eq!(metadata.license(), Some("MIT".to_owned()));
+        assert_eq!(metadata.homepage(), Some("https://github.com/foo/foo"));
+    }
+
+    #[test]
+    fn joins_continuation_lines() {
+        let metadata = CoreMetadata::parse(
+            "Name: foo
+License: Copyright (c) 2020
+        |
+        |Permission is hereby granted
+Version: 1.0
+",
+        );
+        assert_eq!(
+            metadata.license.as_deref(),
+            Some("Copyright (c) 2020\n\nPermission is hereby granted")
+        );
+        assert_eq!(metadata.version.as_deref(), Some("1.0"));
+        assert_eq!(metadata.license(), None);
+    }
+}
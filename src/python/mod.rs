@@ -1,46 +1,414 @@
-use anyhow::Result;
+use std::path::Path;
 
-use crate::core::{Dependency, DependencyOverrides, FetchDependency, Resolve};
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use reqwest::blocking::Client;
 
+use crate::core::{
+    file_name, find_lockfile, load_json_file, load_toml_file, Dependency, DependencyOverride,
+    DependencyOverrides, DependencySpec, DependencySpecs, FetchDependency, Language, Registry,
+    Resolve, Source,
+};
+
+pub mod distribution;
+pub mod license;
+pub mod metadata;
+pub mod pdmlock;
+pub mod pep440;
+pub mod pep508;
+pub mod pipfile;
+pub mod pipfilelock;
+pub mod poetrylock;
 pub mod pypi;
 pub mod pyproject;
+pub mod requirements;
+pub mod simple;
+pub mod uvlock;
 
+use distribution::Distribution;
+use pdmlock::PdmLock;
+use pep508::normalize_name;
+use pipfile::Pipfile;
+use pipfilelock::PipfileLock;
+use poetrylock::PoetryLock;
 use pypi::{Info, Package, PyPI};
+use pyproject::PyProject;
+use requirements::Requirements;
+use simple::{IndexPackage, SimpleIndex};
+use uvlock::UvLock;
+
+const POETRY_LOCKFILE_NAME: &str = "poetry.lock";
+const UV_LOCKFILE_NAME: &str = "uv.lock";
+const PDM_LOCKFILE_NAME: &str = "pdm.lock";
+/// Lockfiles which may belong to a `pyproject.toml`, in order of preference.
+const PYPROJECT_LOCKFILE_NAMES: [&str; 3] =
+    [POETRY_LOCKFILE_NAME, UV_LOCKFILE_NAME, PDM_LOCKFILE_NAME];
+const PIPFILE_NAME: &str = "Pipfile";
+const PIPFILE_LOCKFILE_NAME: &str = "Pipfile.lock";
+const PYPI_INDEX_URLS: [&str; 2] = ["https://pypi.org/simple", "https://pypi.python.org/simple"];
+
+/// The source of packages from an index URL, which may just be PyPI.
+pub fn index_source(index_url: &str) -> Source {
+    if PYPI_INDEX_URLS.contains(&index_url.trim_end_matches('/')) {
+        Source::Default
+    } else {
+        Source::Registry(index_url.to_owned())
+    }
+}
+
+/// A Python manifest, either declaring dependencies or pinning them.
+pub enum Manifest {
+    PyProject(PyProject),
+    PoetryLock(PoetryLock),
+    Requirements(Requirements),
+    Pipfile(Pipfile),
+    PipfileLock(PipfileLock),
+    UvLock(UvLock),
+    PdmLock(PdmLock),
+}
+
+impl Manifest {
+    /// Load the manifest at the given path, or the lockfile beside it if configured.
+    pub fn load(path: &Path, language: &Language) -> Result<Self> {
+        // Requirements files, such as from `pip-compile`, are often pinned already
+        if is_requirements(path) {
+            return Ok(Manifest::Requirements(Requirements::load(path)?));
+        }
+
+        if language.lockfile {
+            let lockfile_path = find_lockfile(path, lockfile_names(path))?;
+            if lockfile_path != path {
+                info!("Using lockfile {}", lockfile_path.display());
+            }
+            return Self::load_file(&lockfile_path);
+        }
+        Self::load_file(path)
+    }
+
+    /// Indexes searched as well as PyPI for every package.
+    pub fn extra_index_urls(&self) -> &[String] {
+        match self {
+            Manifest::Requirements(requirements) => &requirements.extra_index_urls,
+            _ => &[],
+        }
+    }
+
+    fn load_file(path: &Path) -> Result<Self> {
+        match file_name(path).as_str() {
+            POETRY_LOCKFILE_NAME => Ok(Manifest::PoetryLock(PoetryLock::load(path)?)),
+            PIPFILE_NAME => Ok(Manifest::Pipfile(load_toml_file(path)?)),
+            PIPFILE_LOCKFILE_NAME => Ok(Manifest::PipfileLock(load_json_file(path)?)),
+            UV_LOCKFILE_NAME => Ok(Manifest::UvLock(load_toml_file(path)?)),
+            PDM_LOCKFILE_NAME => Ok(Manifest::PdmLock(PdmLock::load(path)?)),
+            _ => Ok(Manifest::PyProject(load_toml_file(path)?)),
+        }
+    }
+}
+
+impl DependencySpecs for Manifest {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        let specs = match self {
+            Manifest::PyProject(pyproject) => pyproject.dependency_specs(),
+            Manifest::PoetryLock(poetry_lock) => poetry_lock.dependency_specs(),
+            Manifest::Requirements(requirements) => requirements.dependency_specs(),
+            Manifest::Pipfile(pipfile) => pipfile.dependency_specs(),
+            Manifest::PipfileLock(pipfile_lock) => pipfile_lock.dependency_specs(),
+            Manifest::UvLock(uv_lock) => uv_lock.dependency_specs(),
+            Manifest::PdmLock(pdm_lock) => pdm_lock.dependency_specs(),
+        };
+        // Names are written inconsistently between manifests, so compare them normalized
+        Box::new(specs.map(|mut spec| {
+            spec.name = normalize_name(&spec.name);
+            spec
+        }))
+    }
+}
+
+/// The lockfiles a manifest may have, where a `Pipfile` is only locked by `Pipfile.lock`.
+fn lockfile_names(path: &Path) -> &'static [&'static str] {
+    match file_name(path).as_str() {
+        PIPFILE_NAME | PIPFILE_LOCKFILE_NAME => &[PIPFILE_LOCKFILE_NAME],
+        _ => &PYPROJECT_LOCKFILE_NAMES,
+    }
+}
+
+/// Requirements files are conventionally `requirements.txt`, or `requirements.in` for `pip-compile`.
+fn is_requirements(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension == "txt" || extension == "in")
+        .unwrap_or(false)
+}
+
+/// Indexes from the language config, then any others named by URL in the manifests.
+pub fn load_indexes<'a>(
+    client: &'a Client,
+    language: &Language,
+    specs: &[DependencySpec],
+    extra_index_urls: &[String],
+) -> Result<Vec<SimpleIndex<'a>>> {
+    let mut registries: Vec<_> = language.registries.iter().collect();
+    registries.sort_by_key(|(name, _registry)| name.as_str());
+    let mut indexes = registries
+        .into_iter()
+        .map(|(name, registry)| SimpleIndex::new(client, name, registry))
+        .collect::<Result<Vec<_>>>()?;
+
+    let index_urls = specs
+        .iter()
+        .filter_map(|spec| match &spec.source {
+            Source::Registry(key) if key.contains("://") => Some(key),
+            _ => None,
+        })
+        .chain(extra_index_urls);
+    for index_url in index_urls {
+        if !indexes.iter().any(|index| index.is_index(index_url)) {
+            let registry = Registry {
+                index: index_url.to_owned(),
+                token: None,
+                username: None,
+            };
+            indexes.push(SimpleIndex::new(client, index_url, &registry)?);
+        }
+    }
+    Ok(indexes)
+}
 
 pub struct Resolver<'a> {
     overrides: &'a DependencyOverrides,
     pypi: &'a PyPI<'a>,
+    indexes: &'a [SimpleIndex<'a>],
+    license_dir: Option<&'a Path>,
 }
 
 impl<'a> Resolver<'a> {
-    pub fn new(overrides: &'a DependencyOverrides, pypi: &'a PyPI) -> Self {
-        Self { overrides, pypi }
+    pub fn new(
+        overrides: &'a DependencyOverrides,
+        pypi: &'a PyPI,
+        indexes: &'a [SimpleIndex],
+    ) -> Self {
+        Self {
+            overrides,
+            pypi,
+            indexes,
+            license_dir: None,
+        }
+    }
+
+    /// Save license files bundled with downloaded packages into a directory.
+    pub fn with_license_dir(mut self, license_dir: &'a Path) -> Self {
+        self.license_dir = Some(license_dir);
+        self
+    }
+
+    /// Find an index by name, as in the config, or by URL, as in manifests.
+    fn index(&self, key: &str) -> Result<&SimpleIndex<'a>> {
+        self.indexes
+            .iter()
+            .find(|index| index.name() == key || index.is_index(key))
+            .ok_or_else(|| anyhow!("No configuration found for python index '{}'", key))
     }
 }
 
-impl<'a> Resolve for Resolver<'a> {
-    fn resolve(&self, name: &str) -> Result<Dependency> {
-        let Package { info } = self.pypi.fetch_dependency(name)?;
+impl<'a> Resolver<'a> {
+    fn dependency_override(&self, name: &str) -> Option<&DependencyOverride> {
+        let name = normalize_name(name);
+        self.overrides
+            .iter()
+            .find(|(other, _)| normalize_name(other) == name)
+            .map(|(_, dependency_override)| dependency_override)
+    }
+
+    /// Packages from elsewhere are not on PyPI, so fall back to the repository or leave them unknown.
+    fn resolve_unpublished(&self, spec: &DependencySpec, url: &str) -> Dependency {
+        Dependency {
+            category: "python",
+            name: spec.name.to_owned(),
+            alias: spec.alias.to_owned(),
+            version: spec.version.to_owned(),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            url: url.to_owned(),
+            license: None,
+            license_file: None,
+            homepage: Some(url.to_owned()),
+        }
+    }
+
+    /// Read metadata from the files on an index, or `None` if the index doesn't have the package.
+    fn resolve_index(
+        &self,
+        spec: &DependencySpec,
+        index: &SimpleIndex,
+    ) -> Result<Option<Dependency>> {
+        let package = match index.fetch_package(&spec.name, spec.version.as_deref())? {
+            Some(package) => package,
+            None => return Ok(None),
+        };
+        let IndexPackage {
+            url,
+            version,
+            distribution,
+        } = package;
+        let license_file = self.save_license_files(&spec.name, &distribution)?;
+        let metadata = &distribution.metadata;
+        Ok(Some(Dependency {
+            category: "python",
+            name: metadata
+                .name
+                .to_owned()
+                .unwrap_or_else(|| spec.name.to_owned()),
+            alias: spec.alias.to_owned(),
+            version: Some(version),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            url,
+            license: metadata.license(),
+            license_file,
+            homepage: metadata.homepage().map(ToOwned::to_owned),
+        }))
+    }
+
+    /// Save the license files of a package, returning the path of the first.
+    fn save_license_files(
+        &self,
+        name: &str,
+        distribution: &Distribution,
+    ) -> Result<Option<String>> {
+        let license_dir = match self.license_dir {
+            Some(license_dir) => license_dir,
+            None => return Ok(None),
+        };
+        let directory = match &distribution.metadata.version {
+            Some(version) => license_dir.join(format!("{}-{}", normalize_name(name), version)),
+            None => license_dir.join(normalize_name(name)),
+        };
+        let paths = distribution.save_license_files(&directory)?;
+        Ok(paths.first().map(|path| path.display().to_string()))
+    }
+
+    /// Read metadata from inside a release, when PyPI names no license for it.
+    fn resolve_distribution(
+        &self,
+        name: &str,
+        package: &Package,
+    ) -> Result<(Option<String>, Option<String>)> {
+        let file = match package.distribution_file() {
+            Some(file) => file,
+            None => return Ok((None, None)),
+        };
+        let distribution = match self.pypi.fetch_distribution(file) {
+            Ok(Some(distribution)) => distribution,
+            Ok(None) => return Ok((None, None)),
+            Err(error) => {
+                warn!(
+                    "Failed to read metadata of python package '{}' from {}: {:#}",
+                    name, file.filename, error
+                );
+                return Ok((None, None));
+            }
+        };
+        let license_file = self.save_license_files(name, &distribution)?;
+        Ok((distribution.metadata.license(), license_file))
+    }
+
+    /// Look for packages missing from PyPI in every other index, as pip would.
+    fn resolve_extra(&self, spec: &DependencySpec) -> Option<Dependency> {
+        for index in self.indexes {
+            match self.resolve_index(spec, index) {
+                Ok(Some(dependency)) => return Some(dependency),
+                Ok(None) => {}
+                Err(error) => warn!(
+                    "Failed to read python package '{}' from index '{}': {:#}",
+                    spec.name,
+                    index.name(),
+                    error
+                ),
+            }
+        }
+        None
+    }
+
+    /// Read metadata from PyPI, for the pinned release if there is one.
+    fn resolve_pypi(&self, spec: &DependencySpec) -> Result<Dependency> {
+        let release = match &spec.version {
+            Some(version) => {
+                let release = self.pypi.fetch_version(&spec.name, version)?;
+                if release.is_none() {
+                    warn!(
+                        "No release {} found for python package '{}'",
+                        version, spec.name
+                    );
+                }
+                release
+            }
+            None => Some(self.pypi.fetch_dependency(&spec.name)?),
+        };
+        let (license, license_file, homepage) = match &release {
+            Some(package) => {
+                let mut license = package.info.license();
+                let mut license_file = None;
+                if license.is_none() {
+                    let (distribution_license, distribution_license_file) =
+                        self.resolve_distribution(&spec.name, package)?;
+                    license = distribution_license;
+                    license_file = distribution_license_file;
+                }
+                let homepage = package.info.homepage().map(ToOwned::to_owned);
+                (license, license_file, homepage)
+            }
+            None => (None, None, None),
+        };
+        // The latest release still locates the project, but its license may differ
+        let package = match release {
+            Some(package) => package,
+            None => self.pypi.fetch_dependency(&spec.name)?,
+        };
+        let Package { info, .. } = package;
         let Info {
-            name,
-            project_url,
-            license,
-            home_page,
+            name, project_url, ..
         } = info;
+        let url = project_url.unwrap_or_else(|| format!("https://pypi.org/project/{}/", name));
 
-        let mut dependency = Dependency {
+        Ok(Dependency {
             category: "python",
             name,
-            url: project_url,
-            license: if license.is_empty() {
-                None
-            } else {
-                Some(license)
+            alias: spec.alias.to_owned(),
+            version: spec.version.to_owned(),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            url,
+            license,
+            license_file,
+            homepage,
+        })
+    }
+}
+
+impl<'a> Resolve for Resolver<'a> {
+    fn resolve(&self, spec: &DependencySpec) -> Result<Dependency> {
+        let mut dependency = match &spec.source {
+            Source::Default => match self.resolve_pypi(spec) {
+                Ok(dependency) => dependency,
+                Err(error) => self.resolve_extra(spec).ok_or(error)?,
             },
-            homepage: Some(home_page),
+            Source::Git { url, .. } => self.resolve_unpublished(spec, url),
+            Source::Registry(key) => {
+                let index = self.index(key)?;
+                match self.resolve_index(spec, index)? {
+                    Some(dependency) => dependency,
+                    None => {
+                        warn!(
+                            "No release found for python package '{}' in index '{}'",
+                            spec.name,
+                            index.name()
+                        );
+                        self.resolve_unpublished(spec, &index.url(&spec.name))
+                    }
+                }
+            }
         };
 
-        if let Some(dependency_override) = self.overrides.get(&dependency.name) {
+        if let Some(dependency_override) = self.dependency_override(&dependency.name) {
             if let Some(license) = &dependency_override.license {
                 dependency.license = Some(license.to_owned());
             };
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Result;
+use log::warn;
+use serde_derive::Deserialize;
+
+use crate::core::{load_toml_file, DependencySpec, DependencySpecs, Scope, Source};
+use crate::python::pyproject::PyProject;
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// Every group requiring the package, where `default` holds the project dependencies.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub git: Option<String>,
+    #[serde(default)]
+    pub revision: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl Package {
+    fn scope(&self, optional_groups: &HashSet<String>) -> Scope {
+        // Lockfiles before PDM 2.8 record no groups
+        if self.groups.is_empty() || self.groups.iter().any(|group| group == "default") {
+            Scope::Normal
+        } else if self
+            .groups
+            .iter()
+            .any(|group| optional_groups.contains(group))
+        {
+            Scope::Optional
+        } else {
+            Scope::Dev
+        }
+    }
+
+    /// Where this package was installed from, or `None` for local paths and URLs.
+    fn source(&self) -> Option<Source> {
+        if let Some(url) = &self.git {
+            return Some(Source::Git {
+                url: url.to_owned(),
+                reference: self.revision.to_owned(),
+            });
+        }
+        if let Some(url) = &self.url {
+            warn!(
+                "Skipping python package '{}' installed from URL {}",
+                self.name, url
+            );
+            return None;
+        }
+        if self.path.is_some() {
+            return None;
+        }
+        Some(Source::Default)
+    }
+}
+
+/// PDM's lockfile, pinning every package for each dependency group.
+#[derive(Deserialize)]
+pub struct PdmLock {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<Package>,
+    /// Groups which are extras of the project, rather than development groups.
+    #[serde(skip)]
+    pub optional_groups: HashSet<String>,
+}
+
+impl PdmLock {
+    /// Load the lockfile, along with the extras declared by the project beside it.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut pdm_lock: PdmLock = load_toml_file(path)?;
+        let pyproject_path = path.with_file_name("pyproject.toml");
+        if pyproject_path.is_file() {
+            let pyproject: PyProject = load_toml_file(&pyproject_path)?;
+            if let Some(project) = pyproject.project {
+                pdm_lock.optional_groups = project.optional_dependencies.into_keys().collect();
+            }
+        }
+        Ok(pdm_lock)
+    }
+}
+
+impl DependencySpecs for PdmLock {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(self.packages.iter().filter_map(move |package| {
+            Some(
+                DependencySpec::new(&package.name)
+                    .with_version(&package.version)
+                    .with_scope(package.scope(&self.optional_groups))
+                    .with_source(package.source()?),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_groups() {
+        let mut pdm_lock: PdmLock = toml::from_str(
+            r#"
+[metadata]
+groups = ["default", "socks", "test"]
+
+[[package]]
+name = "requests"
+version = "2.31.0"
+groups = ["default", "test"]
+
+[[package]]
+name = "pysocks"
+version = "1.7.1"
+groups = ["socks"]
+
+[[package]]
+name = "pytest"
+version = "7.4.0"
+groups = ["test"]
+git = "https://github.com/pytest-dev/pytest.git"
+revision = "0123abcd"
+
+[[package]]
+name = "local"
+version = "0.1.0"
+path = "../local"
+"#,
+        )
+        .unwrap();
+        pdm_lock.optional_groups.insert("socks".to_owned());
+        let specs: Vec<_> = pdm_lock.dependency_specs().collect();
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("requests").with_version("2.31.0"),
+                DependencySpec::new("pysocks")
+                    .with_version("1.7.1")
+                    .with_scope(Scope::Optional),
+                DependencySpec::new("pytest")
+                    .with_version("7.4.0")
+                    .with_scope(Scope::Dev)
+                    .with_source(Source::Git {
+                        url: "https://github.com/pytest-dev/pytest.git".to_owned(),
+                        reference: Some("0123abcd".to_owned()),
+                    }),
+            ]
+        );
+    }
+}
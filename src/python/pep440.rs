@@ -0,0 +1,272 @@
+use std::cmp::Ordering;
+
+/// A pre-release phase, in the order they are released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Alpha,
+    Beta,
+    ReleaseCandidate,
+}
+
+/// A part of a local version label, where numbers sort after text.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LocalSegment {
+    Text(String),
+    Number(u64),
+}
+
+/// A version identifier, as defined by PEP 440.
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub epoch: u64,
+    /// Release numbers, without trailing zeros so that `1.0` equals `1`.
+    pub release: Vec<u64>,
+    pub pre: Option<(Phase, u64)>,
+    pub post: Option<u64>,
+    pub dev: Option<u64>,
+    /// Local version label, as in `1.0+cpu`.
+    pub local: Vec<LocalSegment>,
+}
+
+/// Split a leading number from the rest of the text, which defaults to 0 if missing.
+fn take_number(text: &str) -> (u64, &str) {
+    let end = text
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(text.len());
+    (text[..end].parse().unwrap_or(0), &text[end..])
+}
+
+/// Strip the separator allowed before each segment, as in `1.0-rc.1` or `1.0_post2`.
+fn strip_separator(text: &str) -> &str {
+    text.strip_prefix(['.', '-', '_']).unwrap_or(text)
+}
+
+/// Strip the first of the given spellings of a segment from the text.
+fn strip_any<'a>(text: &'a str, spellings: &[&str]) -> Option<&'a str> {
+    spellings
+        .iter()
+        .find_map(|spelling| text.strip_prefix(spelling))
+}
+
+impl Version {
+    /// Parse a version in any of the forms PEP 440 normalizes, or `None` if it is not valid.
+    pub fn parse(version: &str) -> Option<Self> {
+        let version = version.trim().to_ascii_lowercase();
+        let (public, local) = match version.split_once('+') {
+            Some((public, local)) => (public, Some(local)),
+            None => (version.as_str(), None),
+        };
+        let mut rest = public.strip_prefix('v').unwrap_or(public);
+
+        let mut epoch = 0;
+        if let Some((number, after)) = rest.split_once('!') {
+            epoch = number.parse().ok()?;
+            rest = after;
+        }
+
+        let release_end = rest
+            .find(|character: char| !character.is_ascii_digit() && character != '.')
+            .unwrap_or(rest.len());
+        let release_text = rest[..release_end].trim_end_matches('.');
+        rest = &rest[release_text.len()..];
+        let mut release = release_text
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        while release.last() == Some(&0) {
+            release.pop();
+        }
+
+        let mut pre = None;
+        let phases = [
+            (Phase::Alpha, &["alpha", "a"][..]),
+            (Phase::Beta, &["beta", "b"][..]),
+            (Phase::ReleaseCandidate, &["preview", "pre", "rc", "c"][..]),
+        ];
+        for (phase, spellings) in &phases {
+            if let Some(after) = strip_any(strip_separator(rest), spellings) {
+                let (number, after) = take_number(strip_separator(after));
+                pre = Some((*phase, number));
+                rest = after;
+                break;
+            }
+        }
+
+        let mut post = None;
+        if let Some(after) = strip_any(strip_separator(rest), &["post", "rev", "r"]) {
+            let (number, after) = take_number(strip_separator(after));
+            post = Some(number);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('-') {
+            // An implicit post-release, as in `1.0-1`
+            if after.starts_with(|character: char| character.is_ascii_digit()) {
+                let (number, after) = take_number(after);
+                post = Some(number);
+                rest = after;
+            }
+        }
+
+        let mut dev = None;
+        if let Some(after) = strip_separator(rest).strip_prefix("dev") {
+            let (number, after) = take_number(strip_separator(after));
+            dev = Some(number);
+            rest = after;
+        }
+
+        if !rest.is_empty() {
+            return None;
+        }
+
+        let local = match local {
+            Some(local) => local
+                .split(['.', '-', '_'])
+                .map(|segment| match segment.parse() {
+                    Ok(number) => Some(LocalSegment::Number(number)),
+                    Err(_) if !segment.is_empty() => Some(LocalSegment::Text(segment.to_owned())),
+                    Err(_) => None,
+                })
+                .collect::<Option<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+
+        Some(Self {
+            epoch,
+            release,
+            pre,
+            post,
+            dev,
+            local,
+        })
+    }
+
+    /// Whether this is a pre-release or development release, which installers skip by default.
+    ///
+    /// Local versions, such as `1.0+cpu`, are final releases.
+    pub fn is_prerelease(&self) -> bool {
+        self.pre.is_some() || self.dev.is_some()
+    }
+
+    /// The key versions are ordered by, as in PEP 440.
+    #[allow(clippy::type_complexity)]
+    fn key(
+        &self,
+    ) -> (
+        u64,
+        &[u64],
+        (u8, Option<(Phase, u64)>),
+        Option<u64>,
+        (bool, u64),
+        &[LocalSegment],
+    ) {
+        // Development releases come before pre-releases, which come before the final release
+        let pre = match (self.pre, self.post, self.dev) {
+            (Some(pre), _, _) => (1, Some(pre)),
+            (None, None, Some(_)) => (0, None),
+            (None, _, _) => (2, None),
+        };
+        let dev = match self.dev {
+            Some(dev) => (false, dev),
+            None => (true, 0),
+        };
+        (self.epoch, &self.release, pre, self.post, dev, &self.local)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn parses_segments() {
+        let parsed = version("1!2.0.1rc3.post4.dev5+ubuntu.1");
+        assert_eq!(parsed.epoch, 1);
+        assert_eq!(parsed.release, [2, 0, 1]);
+        assert_eq!(parsed.pre, Some((Phase::ReleaseCandidate, 3)));
+        assert_eq!(parsed.post, Some(4));
+        assert_eq!(parsed.dev, Some(5));
+        assert_eq!(
+            parsed.local,
+            [
+                LocalSegment::Text("ubuntu".to_owned()),
+                LocalSegment::Number(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn normalizes_spellings() {
+        assert_eq!(version("1.0"), version("1"));
+        assert_eq!(version("v1.0.0"), version("1"));
+        assert_eq!(version("1.0-ALPHA.1"), version("1.0a1"));
+        assert_eq!(version("1.0.preview2"), version("1.0rc2"));
+        assert_eq!(version("1.0c2"), version("1.0rc2"));
+        assert_eq!(version("1.0-1"), version("1.0.post1"));
+        assert_eq!(version("1.0_rev1"), version("1.0.post1"));
+        assert_eq!(version("1.0.dev"), version("1.0.dev0"));
+        assert_eq!(version("1.0b"), version("1.0b0"));
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        assert!(Version::parse("").is_none());
+        assert!(Version::parse("latest").is_none());
+        assert!(Version::parse("1.0foo").is_none());
+        assert!(Version::parse("1.0+").is_none());
+    }
+
+    #[test]
+    fn orders_releases() {
+        let ordered = [
+            "1.0.dev1",
+            "1.0a1.dev1",
+            "1.0a1",
+            "1.0a2",
+            "1.0b1",
+            "1.0rc1",
+            "1.0",
+            "1.0+cpu",
+            "1.0+cpu.2",
+            "1.0+1",
+            "1.0.post1.dev1",
+            "1.0.post1",
+            "1.0.1",
+            "1.10",
+            "1!0.1",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn local_versions_are_final() {
+        assert!(!version("1.0+cpu").is_prerelease());
+        assert!(!version("1.0.post1").is_prerelease());
+        assert!(version("1.0rc1").is_prerelease());
+        assert!(version("1.0.dev1").is_prerelease());
+    }
+}
@@ -0,0 +1,300 @@
+use log::{debug, warn};
+
+use crate::core::{DependencySpec, Scope, Source};
+
+/// A dependency specification, as defined by PEP 508.
+#[derive(Debug, Default)]
+pub struct Requirement {
+    pub name: String,
+    pub extras: Vec<String>,
+    /// Version specifier, such as `>=1.0,<2`.
+    pub specifier: Option<String>,
+    /// Direct reference, such as `git+https://github.com/org/repo@v1.0`.
+    pub url: Option<String>,
+    /// Environment marker, such as `python_version < "3.8"`.
+    pub marker: Option<String>,
+}
+
+/// Normalize a package name, as defined by PEP 503, so that differently written names compare equal.
+pub fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for character in name.chars() {
+        if character == '-' || character == '_' || character == '.' {
+            if !normalized.ends_with('-') {
+                normalized.push('-');
+            }
+        } else {
+            normalized.push(character.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+fn is_name_character(character: char) -> bool {
+    character.is_ascii_alphanumeric() || character == '-' || character == '_' || character == '.'
+}
+
+impl Requirement {
+    pub fn parse(requirement: &str) -> Option<Self> {
+        let mut parts = requirement.splitn(2, ';');
+        let requirement = parts.next()?.trim();
+        let marker = parts
+            .next()
+            .map(str::trim)
+            .filter(|marker| !marker.is_empty())
+            .map(ToOwned::to_owned);
+
+        let name_end = requirement
+            .find(|character| !is_name_character(character))
+            .unwrap_or(requirement.len());
+        let name = &requirement[..name_end];
+        if name.is_empty() {
+            return None;
+        }
+        let mut rest = requirement[name_end..].trim_start();
+
+        let mut extras = Vec::new();
+        if let Some(extras_start) = rest.strip_prefix('[') {
+            let extras_end = extras_start.find(']')?;
+            extras = extras_start[..extras_end]
+                .split(',')
+                .map(str::trim)
+                .filter(|extra| !extra.is_empty())
+                .map(ToOwned::to_owned)
+                .collect();
+            rest = extras_start[extras_end + 1..].trim_start();
+        }
+
+        let (specifier, url) = match rest.strip_prefix('@') {
+            Some(url) => (None, Some(url.trim().to_owned())),
+            None => {
+                let specifier = rest.trim_start_matches('(').trim_end_matches(')').trim();
+                let specifier = if specifier.is_empty() {
+                    None
+                } else {
+                    Some(specifier.replace(' ', ""))
+                };
+                (specifier, None)
+            }
+        };
+
+        Some(Self {
+            name: name.to_owned(),
+            extras,
+            specifier,
+            url,
+            marker,
+        })
+    }
+
+    /// The exact version, if the specifier allows only one.
+    pub fn pinned_version(&self) -> Option<&str> {
+        let specifier = self.specifier.as_deref()?;
+        let version = specifier
+            .strip_prefix("===")
+            .or_else(|| specifier.strip_prefix("=="))?;
+        if version.contains(',') || version.contains('*') {
+            return None;
+        }
+        Some(version)
+    }
+
+    /// Whether the requirement only applies when installing an extra of the parent package.
+    pub fn is_extra(&self) -> bool {
+        self.marker
+            .as_ref()
+            .map(|marker| {
+                marker
+                    .split(|character: char| !character.is_ascii_alphanumeric() && character != '_')
+                    .any(|word| word == "extra")
+            })
+            .unwrap_or(false)
+    }
+
+    /// Where the requirement is installed from, or `None` for local paths and archive URLs.
+    pub fn source(&self) -> Option<Source> {
+        let url = match &self.url {
+            Some(url) => url,
+            None => return Some(Source::Default),
+        };
+        // VCS references are like `git+https://host/repo@<reference>`
+        let url = url.strip_prefix("git+")?;
+        let url = url.split('#').next()?;
+        // A reference follows the path, so ignore any user in the host, as in `ssh://git@host`
+        let host_start = url
+            .find("://")
+            .map(|scheme_end| scheme_end + 3)
+            .unwrap_or(0);
+        let path_start = url[host_start..]
+            .find('/')
+            .map(|path_start| host_start + path_start)
+            .unwrap_or(url.len());
+        let (url, reference) = match url.rfind('@') {
+            Some(at) if at > path_start => (&url[..at], Some(url[at + 1..].to_owned())),
+            _ => (url, None),
+        };
+        Some(Source::Git {
+            url: url.to_owned(),
+            reference,
+        })
+    }
+
+    pub fn to_spec(&self, scope: Scope) -> Option<DependencySpec> {
+        let scope = match scope {
+            Scope::Normal if self.is_extra() => Scope::Optional,
+            scope => scope,
+        };
+        let source = match (self.source(), &self.url) {
+            (Some(source), _) => source,
+            (None, Some(url)) if url.starts_with("file:") => {
+                debug!("Skipping local python requirement '{}'", self.name);
+                return None;
+            }
+            // Other VCS and archives have no index to read the license from
+            (None, url) => {
+                warn!(
+                    "Skipping python package '{}' installed from URL {}",
+                    self.name,
+                    url.as_deref().unwrap_or_default()
+                );
+                return None;
+            }
+        };
+        let mut spec = DependencySpec::new(&self.name)
+            .with_scope(scope)
+            .with_source(source);
+        if let Some(specifier) = &self.specifier {
+            spec = spec.with_requirement(specifier);
+        }
+        Some(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_name_only() {
+        let requirement = Requirement::parse("requests").unwrap();
+        assert_eq!(requirement.name, "requests");
+        assert!(requirement.extras.is_empty());
+        assert_eq!(requirement.specifier, None);
+        assert_eq!(requirement.url, None);
+        assert_eq!(requirement.marker, None);
+    }
+
+    #[test]
+    fn parses_extras() {
+        let requirement = Requirement::parse("requests [security, socks] >= 2.8").unwrap();
+        assert_eq!(requirement.name, "requests");
+        assert_eq!(requirement.extras, ["security", "socks"]);
+        assert_eq!(requirement.specifier.as_deref(), Some(">=2.8"));
+    }
+
+    #[test]
+    fn parses_version_specifiers() {
+        let requirement = Requirement::parse("django>=3.2, <4").unwrap();
+        assert_eq!(requirement.specifier.as_deref(), Some(">=3.2,<4"));
+        assert_eq!(requirement.pinned_version(), None);
+
+        let requirement = Requirement::parse("django (==3.2.1)").unwrap();
+        assert_eq!(requirement.specifier.as_deref(), Some("==3.2.1"));
+        assert_eq!(requirement.pinned_version(), Some("3.2.1"));
+
+        let requirement = Requirement::parse("django===3.2.1").unwrap();
+        assert_eq!(requirement.pinned_version(), Some("3.2.1"));
+
+        let requirement = Requirement::parse("django==3.2.*").unwrap();
+        assert_eq!(requirement.pinned_version(), None);
+    }
+
+    #[test]
+    fn parses_markers() {
+        let requirement =
+            Requirement::parse("importlib-metadata>=1.0; python_version < \"3.8\"").unwrap();
+        assert_eq!(requirement.name, "importlib-metadata");
+        assert_eq!(requirement.specifier.as_deref(), Some(">=1.0"));
+        assert_eq!(
+            requirement.marker.as_deref(),
+            Some("python_version < \"3.8\"")
+        );
+        assert!(!requirement.is_extra());
+    }
+
+    #[test]
+    fn marks_extras_as_optional() {
+        let requirement = Requirement::parse("pysocks; extra == \"socks\"").unwrap();
+        assert!(requirement.is_extra());
+        assert_eq!(
+            requirement.to_spec(Scope::Normal).unwrap().scope,
+            Scope::Optional
+        );
+        assert_eq!(requirement.to_spec(Scope::Dev).unwrap().scope, Scope::Dev);
+
+        let requirement =
+            Requirement::parse("pytest; python_version >= \"3.8\" and extra=='test'").unwrap();
+        assert!(requirement.is_extra());
+
+        // Only the `extra` variable itself, not a value containing the word
+        let requirement = Requirement::parse("pywin32; sys_platform == \"extra_win\"").unwrap();
+        assert!(!requirement.is_extra());
+    }
+
+    #[test]
+    fn parses_git_urls() {
+        let requirement =
+            Requirement::parse("yalich @ git+https://github.com/org/yalich@v1.0").unwrap();
+        assert_eq!(requirement.name, "yalich");
+        assert_eq!(requirement.specifier, None);
+        assert_eq!(
+            requirement.url.as_deref(),
+            Some("git+https://github.com/org/yalich@v1.0")
+        );
+        assert_eq!(
+            requirement.source(),
+            Some(Source::Git {
+                url: "https://github.com/org/yalich".to_owned(),
+                reference: Some("v1.0".to_owned()),
+            })
+        );
+
+        let requirement =
+            Requirement::parse("yalich[cli] @ git+ssh://git@github.com/org/yalich#egg=yalich")
+                .unwrap();
+        assert_eq!(requirement.extras, ["cli"]);
+        assert_eq!(
+            requirement.source(),
+            Some(Source::Git {
+                url: "ssh://git@github.com/org/yalich".to_owned(),
+                reference: None,
+            })
+        );
+    }
+
+    #[test]
+    fn skips_other_urls() {
+        for requirement in [
+            "yalich @ hg+https://hg.example.com/yalich",
+            "yalich @ https://example.com/yalich-1.0-py3-none-any.whl",
+            "yalich @ file:///src/yalich",
+        ] {
+            let requirement = Requirement::parse(requirement).unwrap();
+            assert_eq!(requirement.source(), None);
+            assert!(requirement.to_spec(Scope::Normal).is_none());
+        }
+    }
+
+    #[test]
+    fn rejects_missing_names() {
+        assert!(Requirement::parse("").is_none());
+        assert!(Requirement::parse(">=1.0").is_none());
+        assert!(Requirement::parse("requests[security").is_none());
+    }
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize_name("Foo__Bar.baz"), "foo-bar-baz");
+        assert_eq!(normalize_name("typing_extensions"), "typing-extensions");
+    }
+}
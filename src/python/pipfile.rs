@@ -0,0 +1,144 @@
+use serde_derive::Deserialize;
+use toml::value::{Table, Value};
+
+use crate::core::{DependencySpec, DependencySpecs, Scope, Source};
+use crate::python::index_source;
+
+/// A package index, declared by a `[[source]]` table.
+#[derive(Deserialize)]
+pub struct PipfileSource {
+    pub name: String,
+    pub url: String,
+}
+
+/// Find the source of a package from the name of its index, or the first index if unset.
+pub fn package_source(sources: &[PipfileSource], index: Option<&str>) -> Source {
+    let source = match index {
+        Some(index) => sources.iter().find(|source| source.name == index),
+        None => sources.first(),
+    };
+    match (source, index) {
+        (Some(source), _) => index_source(&source.url),
+        (None, Some(index)) => Source::Registry(index.to_owned()),
+        (None, None) => Source::Default,
+    }
+}
+
+/// Pipenv's manifest of direct dependencies.
+#[derive(Deserialize)]
+pub struct Pipfile {
+    #[serde(default)]
+    pub source: Vec<PipfileSource>,
+    #[serde(default)]
+    pub packages: Table,
+    #[serde(default, rename = "dev-packages")]
+    pub dev_packages: Table,
+}
+
+impl Pipfile {
+    fn package_specs<'a>(
+        &'a self,
+        packages: &'a Table,
+        scope: Scope,
+    ) -> impl Iterator<Item = DependencySpec> + 'a {
+        packages
+            .iter()
+            // Filter out local packages with the path or file property
+            .filter(|(_package_name, package)| {
+                package.get("path").is_none() && package.get("file").is_none()
+            })
+            .map(move |(package_name, package)| {
+                let mut spec = DependencySpec::new(package_name).with_scope(scope);
+                let requirement = match package {
+                    Value::String(requirement) => Some(requirement.as_str()),
+                    _ => package.get("version").and_then(Value::as_str),
+                };
+                if let Some(requirement) = requirement.filter(|requirement| *requirement != "*") {
+                    spec = spec.with_requirement(requirement);
+                }
+                let source = match package.get("git").and_then(Value::as_str) {
+                    Some(url) => Source::Git {
+                        url: url.to_owned(),
+                        reference: package
+                            .get("ref")
+                            .and_then(Value::as_str)
+                            .map(ToOwned::to_owned),
+                    },
+                    None => {
+                        package_source(&self.source, package.get("index").and_then(Value::as_str))
+                    }
+                };
+                spec.with_source(source)
+            })
+    }
+}
+
+impl DependencySpecs for Pipfile {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(
+            self.package_specs(&self.packages, Scope::Normal)
+                .chain(self.package_specs(&self.dev_packages, Scope::Dev)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPFILE: &str = r#"
+[[source]]
+name = "pypi"
+url = "https://pypi.org/simple"
+
+[[source]]
+name = "internal"
+url = "https://pypi.example.com/simple"
+
+[packages]
+requests = "*"
+flask = { version = ">=3.0" }
+private = { version = "==1.0.0", index = "internal" }
+forked = { git = "https://github.com/example/forked.git", ref = "v2.0" }
+local = { path = "./local", editable = true }
+
+[dev-packages]
+pytest = "~=7.4"
+"#;
+
+    #[test]
+    fn reads_packages() {
+        let pipfile: Pipfile = toml::from_str(PIPFILE).unwrap();
+        let specs: Vec<_> = pipfile.dependency_specs().collect();
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("flask").with_requirement(">=3.0"),
+                DependencySpec::new("forked").with_source(Source::Git {
+                    url: "https://github.com/example/forked.git".to_owned(),
+                    reference: Some("v2.0".to_owned()),
+                }),
+                DependencySpec::new("private")
+                    .with_requirement("==1.0.0")
+                    .with_source(Source::Registry(
+                        "https://pypi.example.com/simple".to_owned()
+                    )),
+                DependencySpec::new("requests"),
+                DependencySpec::new("pytest")
+                    .with_scope(Scope::Dev)
+                    .with_requirement("~=7.4"),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_package_sources() {
+        let pipfile: Pipfile = toml::from_str(PIPFILE).unwrap();
+        assert_eq!(package_source(&pipfile.source, None), Source::Default);
+        assert_eq!(
+            package_source(&pipfile.source, Some("missing")),
+            Source::Registry("missing".to_owned())
+        );
+        assert_eq!(package_source(&[], None), Source::Default);
+    }
+}
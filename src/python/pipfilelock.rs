@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use serde_derive::Deserialize;
+
+use crate::core::{DependencySpec, DependencySpecs, Scope, Source};
+use crate::python::pipfile::{package_source, PipfileSource};
+
+#[derive(Deserialize)]
+pub struct Package {
+    /// Pinned as a specifier, such as `==1.0.0`.
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub index: Option<String>,
+    #[serde(default)]
+    pub git: Option<String>,
+    #[serde(default)]
+    pub r#ref: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub file: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Meta {
+    #[serde(default)]
+    pub sources: Vec<PipfileSource>,
+}
+
+/// Pipenv's lockfile, pinning every package for the default and develop environments.
+#[derive(Deserialize)]
+pub struct PipfileLock {
+    #[serde(default, rename = "_meta")]
+    pub meta: Meta,
+    #[serde(default)]
+    pub default: BTreeMap<String, Package>,
+    #[serde(default)]
+    pub develop: BTreeMap<String, Package>,
+}
+
+impl PipfileLock {
+    fn package_spec(
+        &self,
+        package_name: &str,
+        package: &Package,
+        scope: Scope,
+    ) -> Option<DependencySpec> {
+        // Filter out local packages
+        if package.path.is_some() || package.file.is_some() {
+            return None;
+        }
+        let source = match &package.git {
+            Some(url) => Source::Git {
+                url: url.to_owned(),
+                reference: package.r#ref.to_owned(),
+            },
+            None => package_source(&self.meta.sources, package.index.as_deref()),
+        };
+        let mut spec = DependencySpec::new(package_name)
+            .with_scope(scope)
+            .with_source(source);
+        if let Some(version) = &package.version {
+            spec = spec.with_version(version.trim_start_matches("=="));
+        }
+        Some(spec)
+    }
+}
+
+impl DependencySpecs for PipfileLock {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(
+            self.default
+                .iter()
+                .filter_map(move |(package_name, package)| {
+                    self.package_spec(package_name, package, Scope::Normal)
+                })
+                .chain(
+                    self.develop
+                        .iter()
+                        // Packages required by both environments are shipped
+                        .filter(move |(package_name, _package)| {
+                            !self.default.contains_key(*package_name)
+                        })
+                        .filter_map(move |(package_name, package)| {
+                            self.package_spec(package_name, package, Scope::Dev)
+                        }),
+                ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_environments() {
+        let pipfile_lock: PipfileLock = serde_json::from_str(
+            r#"{
+                "_meta": {
+                    "sources": [
+                        {"name": "internal", "url": "https://pypi.example.com/simple", "verify_ssl": true}
+                    ]
+                },
+                "default": {
+                    "requests": {"version": "==2.31.0", "hashes": []},
+                    "forked": {"git": "https://github.com/example/forked.git", "ref": "0123abcd"},
+                    "local": {"path": "./local", "editable": true}
+                },
+                "develop": {
+                    "pytest": {"version": "==7.4.0", "index": "internal"},
+                    "requests": {"version": "==2.31.0"}
+                }
+            }"#,
+        )
+        .unwrap();
+        let specs: Vec<_> = pipfile_lock.dependency_specs().collect();
+        let internal = Source::Registry("https://pypi.example.com/simple".to_owned());
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("forked").with_source(Source::Git {
+                    url: "https://github.com/example/forked.git".to_owned(),
+                    reference: Some("0123abcd".to_owned()),
+                }),
+                DependencySpec::new("requests")
+                    .with_version("2.31.0")
+                    .with_source(internal.clone()),
+                DependencySpec::new("pytest")
+                    .with_scope(Scope::Dev)
+                    .with_version("7.4.0")
+                    .with_source(internal),
+            ]
+        );
+    }
+}
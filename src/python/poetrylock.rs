@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use log::warn;
+use serde_derive::Deserialize;
+use toml::value::Table;
+
+use crate::core::{load_toml_file, walk_scopes, DependencySpec, DependencySpecs, Scope, Source};
+use crate::python::pep508::normalize_name;
+use crate::python::pyproject::PyProject;
+
+/// Where a locked package was installed from, if not PyPI.
+#[derive(Deserialize)]
+pub struct PackageSource {
+    pub r#type: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default)]
+    pub resolved_reference: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// Set by Poetry before 1.5, as either `main` or `dev`.
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Set by Poetry 2, listing every group requiring the package.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default)]
+    pub source: Option<PackageSource>,
+    /// Packages this one requires, by name.
+    #[serde(default)]
+    pub dependencies: Table,
+}
+
+impl Package {
+    /// The scope recorded by the lockfile, or `None` if it records neither category nor groups.
+    pub fn scope(&self) -> Option<Scope> {
+        let main = match &self.category {
+            Some(category) => category == "main",
+            None if self.groups.is_empty() => return None,
+            None => self.groups.iter().any(|group| group == "main"),
+        };
+        match (main, self.optional) {
+            (true, false) => Some(Scope::Normal),
+            (true, true) => Some(Scope::Optional),
+            (false, _) => Some(Scope::Dev),
+        }
+    }
+
+    /// Where this package was installed from, or `None` for local paths and files.
+    pub fn source(&self) -> Option<Source> {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return Some(Source::Default),
+        };
+        match source.r#type.as_str() {
+            // Sources from pyproject.toml are recorded by URL and name
+            "legacy" => Some(Source::Registry(
+                source
+                    .url
+                    .to_owned()
+                    .or_else(|| source.reference.to_owned())?,
+            )),
+            "git" => Some(Source::Git {
+                url: source.url.to_owned()?,
+                reference: source
+                    .resolved_reference
+                    .to_owned()
+                    .or_else(|| source.reference.to_owned()),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PoetryLock {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<Package>,
+    /// Scopes found from the project, by normalized name, for lockfiles which don't record them.
+    #[serde(skip)]
+    pub scopes: HashMap<String, Scope>,
+}
+
+impl PoetryLock {
+    /// Load the lockfile, along with the project beside it if the lockfile records no scopes.
+    ///
+    /// Poetry 1.5 stopped writing `category`, and only Poetry 2 writes `groups` instead.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut poetry_lock: PoetryLock = load_toml_file(path)?;
+        if poetry_lock
+            .packages
+            .iter()
+            .all(|package| package.scope().is_none())
+        {
+            let pyproject_path = path.with_file_name("pyproject.toml");
+            if pyproject_path.is_file() {
+                let pyproject: PyProject = load_toml_file(&pyproject_path)?;
+                poetry_lock.scopes = poetry_lock.package_scopes(&pyproject);
+            } else if !poetry_lock.packages.is_empty() {
+                warn!(
+                    "No pyproject.toml beside {}, so every package is reported as normal",
+                    path.display()
+                );
+            }
+        }
+        Ok(poetry_lock)
+    }
+
+    /// Walk the locked graph from the project dependencies, finding the scope of each package.
+    fn package_scopes(&self, pyproject: &PyProject) -> HashMap<String, Scope> {
+        let indices: HashMap<String, usize> = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(index, package)| (normalize_name(&package.name), index))
+            .collect();
+        let lookup = |name: &str| indices.get(&normalize_name(name)).copied();
+
+        let roots = pyproject
+            .dependency_specs()
+            .filter_map(|spec| Some((lookup(&spec.name)?, spec.scope)));
+        walk_scopes(roots, |index, scope| {
+            self.packages[index]
+                .dependencies
+                .keys()
+                .filter_map(|dependency| lookup(dependency))
+                .map(move |index| (index, scope))
+        })
+        .into_iter()
+        .map(|(index, scope)| (normalize_name(&self.packages[index].name), scope))
+        .collect()
+    }
+
+    fn scope(&self, package: &Package) -> Scope {
+        package
+            .scope()
+            .or_else(|| self.scopes.get(&normalize_name(&package.name)).copied())
+            .unwrap_or_default()
+    }
+}
+
+impl DependencySpecs for PoetryLock {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(self.packages.iter().filter_map(move |package| {
+            Some(
+                DependencySpec::new(&package.name)
+                    .with_version(&package.version)
+                    .with_scope(self.scope(package))
+                    .with_source(package.source()?),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes(poetry_lock: &PoetryLock) -> Vec<(String, Scope)> {
+        poetry_lock
+            .dependency_specs()
+            .map(|spec| (spec.name, spec.scope))
+            .collect()
+    }
+
+    #[test]
+    fn reads_categories() {
+        let poetry_lock: PoetryLock = toml::from_str(
+            r#"
+[[package]]
+name = "requests"
+version = "2.31.0"
+category = "main"
+optional = false
+
+[[package]]
+name = "PySocks"
+version = "1.7.1"
+category = "main"
+optional = true
+
+[[package]]
+name = "pytest"
+version = "7.4.0"
+category = "dev"
+optional = false
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            scopes(&poetry_lock),
+            [
+                ("requests".to_owned(), Scope::Normal),
+                ("PySocks".to_owned(), Scope::Optional),
+                ("pytest".to_owned(), Scope::Dev),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_groups() {
+        let poetry_lock: PoetryLock = toml::from_str(
+            r#"
+[[package]]
+name = "requests"
+version = "2.31.0"
+groups = ["main", "test"]
+
+[[package]]
+name = "pytest"
+version = "7.4.0"
+groups = ["test"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            scopes(&poetry_lock),
+            [
+                ("requests".to_owned(), Scope::Normal),
+                ("pytest".to_owned(), Scope::Dev),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_sources() {
+        let poetry_lock: PoetryLock = toml::from_str(
+            r#"
+[[package]]
+name = "private"
+version = "1.0.0"
+
+[package.source]
+type = "legacy"
+url = "https://pypi.example.com/simple"
+reference = "internal"
+
+[[package]]
+name = "forked"
+version = "2.0.0"
+
+[package.source]
+type = "git"
+url = "https://github.com/example/forked.git"
+reference = "main"
+resolved_reference = "0123abcd"
+
+[[package]]
+name = "local"
+version = "0.1.0"
+
+[package.source]
+type = "directory"
+url = "../local"
+"#,
+        )
+        .unwrap();
+        let sources: Vec<_> = poetry_lock
+            .dependency_specs()
+            .map(|spec| (spec.name, spec.source))
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                (
+                    "private".to_owned(),
+                    Source::Registry("https://pypi.example.com/simple".to_owned())
+                ),
+                (
+                    "forked".to_owned(),
+                    Source::Git {
+                        url: "https://github.com/example/forked.git".to_owned(),
+                        reference: Some("0123abcd".to_owned()),
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_scopes_from_pyproject() {
+        let mut poetry_lock: PoetryLock = toml::from_str(
+            r#"
+[[package]]
+name = "requests"
+version = "2.31.0"
+
+[package.dependencies]
+urllib3 = ">=1.21.1,<3"
+
+[[package]]
+name = "urllib3"
+version = "2.0.4"
+
+[[package]]
+name = "pytest"
+version = "7.4.0"
+
+[package.dependencies]
+Requests = "*"
+pluggy = ">=0.12,<2.0"
+
+[[package]]
+name = "pluggy"
+version = "1.2.0"
+"#,
+        )
+        .unwrap();
+        let pyproject: PyProject = toml::from_str(
+            r#"
+[tool.poetry.dependencies]
+python = "^3.8"
+requests = "^2.31"
+
+[tool.poetry.group.test.dependencies]
+pytest = "^7.4"
+"#,
+        )
+        .unwrap();
+        poetry_lock.scopes = poetry_lock.package_scopes(&pyproject);
+        assert_eq!(
+            scopes(&poetry_lock),
+            [
+                ("requests".to_owned(), Scope::Normal),
+                ("urllib3".to_owned(), Scope::Normal),
+                ("pytest".to_owned(), Scope::Dev),
+                ("pluggy".to_owned(), Scope::Dev),
+            ]
+        );
+    }
+}
@@ -1,21 +1,129 @@
-use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
 use reqwest::blocking::Client;
+use reqwest::StatusCode;
 use serde_derive::Deserialize;
 use url::Url;
 
 use crate::core::FetchDependency;
+use crate::github::homepage_to_repo;
+use crate::python::distribution::Distribution;
+use crate::python::license::select_license;
+
+/// Files larger than this are not downloaded for their metadata.
+const MAX_DOWNLOAD_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Labels of `project_urls` which link to the source repository, once normalized.
+const REPOSITORY_LABELS: [&str; 5] = ["source", "sourcecode", "repository", "code", "github"];
 
+/// Metadata as published by the package, where any field may be missing or `null`.
 #[derive(Debug, Deserialize)]
 pub struct Info {
-    pub license: String,
+    #[serde(default)]
+    pub license: Option<String>,
+    /// SPDX expression, as defined by PEP 639.
+    #[serde(default)]
+    pub license_expression: Option<String>,
+    #[serde(default)]
+    pub classifiers: Option<Vec<String>>,
     pub name: String,
-    pub project_url: String,
-    pub home_page: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub project_url: Option<String>,
+    #[serde(default)]
+    pub home_page: Option<String>,
+    #[serde(default)]
+    pub project_urls: Option<HashMap<String, String>>,
+}
+
+/// Normalize a `project_urls` label, as in the core metadata specification.
+fn normalize_label(label: &str) -> String {
+    label
+        .chars()
+        .filter(|character| character.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The source repository, if linked from the project URLs.
+pub fn find_repository<'a>(
+    project_urls: impl Iterator<Item = (&'a str, &'a str)> + Clone,
+) -> Option<&'a str> {
+    REPOSITORY_LABELS.iter().find_map(|label| {
+        project_urls
+            .clone()
+            .find(|(other, _url)| normalize_label(other) == *label)
+            .map(|(_label, url)| url)
+    })
+}
+
+/// The homepage, preferring the repository if the homepage is not on Github, such as
+/// a documentation site.
+pub fn select_homepage<'a>(
+    home_page: Option<&'a str>,
+    repository: Option<&'a str>,
+) -> Option<&'a str> {
+    let home_page = home_page.filter(|home_page| !home_page.trim().is_empty());
+    match home_page {
+        Some(home_page) if homepage_to_repo(home_page).is_some() => Some(home_page),
+        _ => repository.or(home_page),
+    }
+}
+
+impl Info {
+    pub fn license(&self) -> Option<String> {
+        select_license(
+            self.license_expression.as_deref(),
+            self.classifiers.iter().flatten().map(String::as_str),
+            self.license.as_deref(),
+        )
+    }
+
+    pub fn homepage(&self) -> Option<&str> {
+        let repository = find_repository(
+            self.project_urls
+                .iter()
+                .flatten()
+                .map(|(label, url)| (label.as_str(), url.as_str())),
+        );
+        select_homepage(self.home_page.as_deref(), repository)
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Package {
     pub info: Info,
+    /// Files of the release.
+    #[serde(default)]
+    pub urls: Option<Vec<ReleaseFile>>,
+}
+
+/// A wheel or sdist of a release.
+#[derive(Debug, Deserialize)]
+pub struct ReleaseFile {
+    pub filename: String,
+    pub url: String,
+    #[serde(default)]
+    pub packagetype: Option<String>,
+    #[serde(default)]
+    pub size: Option<u64>,
+}
+
+impl Package {
+    /// The file to read metadata from, preferring small wheels, as their metadata is reliable.
+    pub fn distribution_file(&self) -> Option<&ReleaseFile> {
+        self.urls
+            .iter()
+            .flatten()
+            .filter(|file| file.size.unwrap_or(0) <= MAX_DOWNLOAD_SIZE)
+            .min_by_key(|file| {
+                let wheel = file.packagetype.as_deref() == Some("bdist_wheel");
+                (!wheel, file.size.unwrap_or(0))
+            })
+    }
 }
 
 pub struct PyPI<'a> {
@@ -26,17 +134,132 @@ impl<'a> PyPI<'a> {
     pub fn new(client: &'a Client) -> Self {
         Self { client }
     }
-}
 
-impl<'a> FetchDependency<Package> for PyPI<'a> {
-    fn fetch_dependency(&self, package_name: &str) -> Result<Package> {
-        let url = Url::parse(&format!("https://pypi.org/pypi/{}/json", package_name))
-            .with_context(|| format!("Invalid URL for pypi package '{}'.", package_name))?;
-        self.client
+    /// Fetch package metadata, or `None` if not found.
+    fn fetch(&self, path: &str) -> Result<Option<Package>> {
+        let url = Url::parse(&format!("https://pypi.org/pypi/{}/json", path))
+            .with_context(|| format!("Invalid URL for pypi package '{}'.", path))?;
+        let response = self
+            .client
             .get(url)
             .send()
-            .with_context(|| format!("Pypi request for '{}' failed.", package_name))?
+            .with_context(|| format!("Pypi request for '{}' failed.", path))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        response
+            .error_for_status()
+            .with_context(|| format!("Pypi request for '{}' failed.", path))?
             .json()
-            .with_context(|| format!("JSON deserialization for '{}' failed.", package_name))
+            .map(Some)
+            .with_context(|| format!("JSON deserialization for '{}' failed.", path))
+    }
+
+    /// Download a release file and read its metadata.
+    pub fn fetch_distribution(&self, file: &ReleaseFile) -> Result<Option<Distribution>> {
+        debug!("Downloading {} for its metadata", file.filename);
+        let bytes = self
+            .client
+            .get(&file.url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Download of '{}' failed.", file.filename))?
+            .bytes()?;
+        Distribution::read(&file.filename, &bytes)
+    }
+
+    /// Metadata of a specific release, rather than the latest, or `None` if not found.
+    pub fn fetch_version(&self, package_name: &str, version: &str) -> Result<Option<Package>> {
+        self.fetch(&format!("{}/{}", package_name, version))
+    }
+}
+
+impl<'a> FetchDependency<Package> for PyPI<'a> {
+    fn fetch_dependency(&self, package_name: &str) -> Result<Package> {
+        self.fetch(package_name)?
+            .ok_or_else(|| anyhow!("Pypi package '{}' not found.", package_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tolerates_null_fields() {
+        let package: Package = serde_json::from_str(
+            r#"{
+                "info": {
+                    "name": "foo",
+                    "license": null,
+                    "home_page": null,
+                    "classifiers": ["License :: OSI Approved :: MIT License"],
+                    "project_urls": null
+                },
+                "urls": null
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(package.info.license(), Some("MIT".to_owned()));
+        assert_eq!(package.info.homepage(), None);
+        assert!(package.distribution_file().is_none());
+    }
+
+    #[test]
+    fn finds_repository_in_project_urls() {
+        let project_urls = [
+            ("Documentation", "https://foo.readthedocs.io"),
+            ("Code", "https://gitlab.com/foo/foo"),
+            ("Source Code", "https://github.com/foo/foo"),
+        ];
+        assert_eq!(
+            find_repository(project_urls.iter().copied()),
+            Some("https://github.com/foo/foo")
+        );
+        assert_eq!(
+            find_repository(project_urls[..2].iter().copied()),
+            Some("https://gitlab.com/foo/foo")
+        );
+        assert_eq!(find_repository(project_urls[..1].iter().copied()), None);
+    }
+
+    #[test]
+    fn prefers_github_homepages() {
+        let repository = Some("https://github.com/foo/foo");
+        assert_eq!(
+            select_homepage(Some("https://github.com/foo/bar"), repository),
+            Some("https://github.com/foo/bar")
+        );
+        assert_eq!(
+            select_homepage(Some("https://foo.readthedocs.io"), repository),
+            repository
+        );
+        assert_eq!(select_homepage(Some(" "), None), None);
+        assert_eq!(
+            select_homepage(Some("https://foo.org"), None),
+            Some("https://foo.org")
+        );
+    }
+
+    #[test]
+    fn prefers_small_wheels() {
+        let package: Package = serde_json::from_str(
+            r#"{
+                "info": {"name": "foo"},
+                "urls": [
+                    {"filename": "foo-1.0.tar.gz", "url": "https://files/foo-1.0.tar.gz", "packagetype": "sdist", "size": 10},
+                    {"filename": "foo-1.0-py3-none-any.whl", "url": "https://files/a.whl", "packagetype": "bdist_wheel", "size": 200},
+                    {"filename": "foo-1.0-cp38-win32.whl", "url": "https://files/b.whl", "packagetype": "bdist_wheel", "size": 100},
+                    {"filename": "foo-1.0-cp38-linux.whl", "url": "https://files/c.whl", "packagetype": "bdist_wheel", "size": 100000000}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            package
+                .distribution_file()
+                .map(|file| file.filename.as_str()),
+            Some("foo-1.0-cp38-win32.whl")
+        );
     }
 }
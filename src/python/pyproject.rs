@@ -1,33 +1,264 @@
-use std::convert::AsRef;
+use std::collections::BTreeMap;
 
+use log::{debug, warn};
 use serde_derive::Deserialize;
+use toml::value::{Table, Value};
 
-use crate::core::DependencyNames;
+use crate::core::{DependencySpec, DependencySpecs, Scope, Source};
+use crate::python::index_source;
+use crate::python::pep508::Requirement;
 
 #[derive(Deserialize)]
-pub struct Poetry {
-    pub dependencies: toml::value::Table,
+pub struct Group {
+    #[serde(default)]
+    pub dependencies: Table,
+}
+
+/// A package index, declared by a `[[tool.poetry.source]]` table.
+#[derive(Deserialize)]
+pub struct PoetrySource {
+    pub name: String,
+    #[serde(default)]
+    pub url: Option<String>,
 }
 
 #[derive(Deserialize)]
+pub struct Poetry {
+    #[serde(default)]
+    pub dependencies: Table,
+    #[serde(default, rename = "dev-dependencies")]
+    pub dev_dependencies: Table,
+    #[serde(default)]
+    pub group: BTreeMap<String, Group>,
+    #[serde(default)]
+    pub source: Vec<PoetrySource>,
+}
+
+#[derive(Deserialize, Default)]
 pub struct Tool {
-    pub poetry: Poetry,
+    #[serde(default)]
+    pub poetry: Option<Poetry>,
+}
+
+/// The standard `[project]` table, as defined by PEP 621.
+#[derive(Deserialize)]
+pub struct Project {
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default, rename = "optional-dependencies")]
+    pub optional_dependencies: BTreeMap<String, Vec<String>>,
 }
 
 #[derive(Deserialize)]
 pub struct PyProject {
+    #[serde(default)]
+    pub project: Option<Project>,
+    #[serde(default)]
     pub tool: Tool,
 }
 
-impl DependencyNames for PyProject {
-    fn dependency_names<'a>(&'a self) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+impl Poetry {
+    fn package_specs<'a>(
+        &'a self,
+        dependencies: &'a Table,
+        scope: Scope,
+    ) -> impl Iterator<Item = DependencySpec> + 'a {
+        dependencies
+            .iter()
+            .filter(|(dependency_name, _dependency)| dependency_name.as_str() != "python")
+            .filter_map(move |(dependency_name, dependency)| {
+                self.package_spec(dependency_name, dependency, scope)
+            })
+    }
+
+    /// The spec for a dependency, given as a requirement or a table, or `None` if it is installed
+    /// from a path or an archive.
+    fn package_spec(&self, name: &str, dependency: &Value, scope: Scope) -> Option<DependencySpec> {
+        // Optional dependencies are only installed as part of an extra
+        let optional = dependency.get("optional") == Some(&Value::Boolean(true));
+        let scope = if optional { scope.optional() } else { scope };
+        let mut spec = DependencySpec::new(name).with_scope(scope);
+
+        let requirement = match dependency {
+            Value::String(requirement) => Some(requirement.as_str()),
+            _ => dependency.get("version").and_then(Value::as_str),
+        };
+        if let Some(requirement) = requirement {
+            spec = spec.with_requirement(requirement);
+        }
+
+        if dependency.get("path").is_some() {
+            debug!("Skipping local python dependency '{}'", name);
+            return None;
+        }
+        if let Some(url) = dependency.get("git").and_then(Value::as_str) {
+            let reference = ["rev", "tag", "branch"]
+                .iter()
+                .find_map(|key| dependency.get(key).and_then(Value::as_str));
+            return Some(spec.with_source(Source::Git {
+                url: url.to_owned(),
+                reference: reference.map(ToOwned::to_owned),
+            }));
+        }
+        // Archives have no index to read the license from
+        if let Some(url) = dependency.get("url").and_then(Value::as_str) {
+            warn!(
+                "Skipping python package '{}' installed from URL {}",
+                name, url
+            );
+            return None;
+        }
+        if let Some(source) = dependency.get("source").and_then(Value::as_str) {
+            spec = spec.with_source(self.source(source));
+        }
+        Some(spec)
+    }
+
+    /// Find a source by name, which is recorded by URL if declared.
+    fn source(&self, name: &str) -> Source {
+        let url = self
+            .source
+            .iter()
+            .find(|source| source.name == name)
+            .and_then(|source| source.url.as_deref());
+        match url {
+            Some(url) => index_source(url),
+            None => Source::Registry(name.to_owned()),
+        }
+    }
+}
+
+fn requirement_specs(
+    requirements: &[String],
+    scope: Scope,
+) -> impl Iterator<Item = DependencySpec> + '_ {
+    requirements.iter().filter_map(move |requirement| {
+        let parsed = Requirement::parse(requirement);
+        if parsed.is_none() {
+            warn!("Skipping unsupported python requirement '{}'", requirement);
+        }
+        parsed?.to_spec(scope)
+    })
+}
+
+impl DependencySpecs for Project {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
         Box::new(
-            self.tool
-                .poetry
-                .dependencies
-                .keys()
-                .filter(|dependency_name| dependency_name.as_str() != "python")
-                .map(AsRef::as_ref),
+            requirement_specs(&self.dependencies, Scope::Normal).chain(
+                self.optional_dependencies
+                    .values()
+                    .flat_map(|requirements| requirement_specs(requirements, Scope::Optional)),
+            ),
         )
     }
 }
+
+impl DependencySpecs for Poetry {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(
+            self.package_specs(&self.dependencies, Scope::Normal)
+                .chain(self.package_specs(&self.dev_dependencies, Scope::Dev))
+                .chain(self.group.iter().flat_map(move |(group_name, group)| {
+                    // Any group besides main holds tooling, such as tests or docs
+                    let scope = match group_name.as_str() {
+                        "main" => Scope::Normal,
+                        _ => Scope::Dev,
+                    };
+                    self.package_specs(&group.dependencies, scope)
+                })),
+        )
+    }
+}
+
+impl DependencySpecs for PyProject {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        // Poetry projects may declare their dependencies in either table, or both
+        Box::new(
+            self.project
+                .iter()
+                .flat_map(|project| project.dependency_specs())
+                .chain(
+                    self.tool
+                        .poetry
+                        .iter()
+                        .flat_map(|poetry| poetry.dependency_specs()),
+                ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(pyproject: &str) -> Vec<DependencySpec> {
+        toml::from_str::<PyProject>(pyproject)
+            .unwrap()
+            .dependency_specs()
+            .collect()
+    }
+
+    #[test]
+    fn reads_poetry_dependencies() {
+        let specs = specs(
+            r#"
+[tool.poetry.dependencies]
+python = "^3.8"
+requests = "^2.25"
+black = { version = "==22.1.0", optional = true }
+private = { version = "^1.0", source = "internal" }
+local = { path = "../local", develop = true }
+forked = { git = "https://github.com/user/forked.git", tag = "v1.0" }
+archive = { url = "https://example.com/archive-1.0.tar.gz" }
+
+[tool.poetry.group.test.dependencies]
+pytest = "*"
+
+[[tool.poetry.source]]
+name = "internal"
+url = "https://pypi.example.com/simple/"
+"#,
+        );
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("black")
+                    .with_requirement("==22.1.0")
+                    .with_scope(Scope::Optional),
+                DependencySpec::new("forked").with_source(Source::Git {
+                    url: "https://github.com/user/forked.git".to_owned(),
+                    reference: Some("v1.0".to_owned()),
+                }),
+                DependencySpec::new("private")
+                    .with_requirement("^1.0")
+                    .with_source(Source::Registry(
+                        "https://pypi.example.com/simple/".to_owned()
+                    )),
+                DependencySpec::new("requests").with_requirement("^2.25"),
+                DependencySpec::new("pytest")
+                    .with_requirement("*")
+                    .with_scope(Scope::Dev),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_project_dependencies() {
+        let specs = specs(
+            r#"
+[project]
+dependencies = ["requests>=2.25", "local @ file:///src/local"]
+
+[project.optional-dependencies]
+docs = ["sphinx"]
+"#,
+        );
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("requests").with_requirement(">=2.25"),
+                DependencySpec::new("sphinx").with_scope(Scope::Optional),
+            ]
+        );
+    }
+}
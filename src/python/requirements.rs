@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use log::{debug, warn};
+
+use crate::core::{load_file, DependencySpec, DependencySpecs, Scope, Source};
+use crate::python::index_source;
+use crate::python::pep508::{normalize_name, Requirement};
+
+/// A line of a requirements file, after joining continuations and removing comments.
+enum Line {
+    Include(PathBuf),
+    Constraint(PathBuf),
+    IndexUrl(String),
+    ExtraIndexUrl(String),
+    Requirement(Requirement),
+}
+
+/// A pip requirements file, along with any files it includes.
+#[derive(Default)]
+pub struct Requirements {
+    pub requirements: Vec<Requirement>,
+    /// Requirements from constraint files, which only pin the versions of other requirements.
+    pub constraints: Vec<Requirement>,
+    /// Index replacing PyPI, as set by `--index-url`.
+    pub index_url: Option<String>,
+    /// Indexes searched as well as PyPI, as set by `--extra-index-url`.
+    pub extra_index_urls: Vec<String>,
+}
+
+impl Requirements {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut requirements = Self::default();
+        requirements.include(path, false, &mut HashSet::new())?;
+        Ok(requirements)
+    }
+
+    fn include(
+        &mut self,
+        path: &Path,
+        constraint: bool,
+        visited: &mut HashSet<PathBuf>,
+    ) -> Result<()> {
+        // Files may include each other, so only read each once
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        if !visited.insert(canonical) {
+            return Ok(());
+        }
+        debug!("Loading requirements file {}", path.display());
+        let buffer = load_file(path)?;
+        // Included files are relative to the file including them
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        for line in logical_lines(&buffer) {
+            match parse_line(&line) {
+                Some(Line::Include(include)) => {
+                    self.include(&directory.join(include), constraint, visited)?
+                }
+                Some(Line::Constraint(include)) => {
+                    self.include(&directory.join(include), true, visited)?
+                }
+                Some(Line::IndexUrl(index_url)) => self.index_url = Some(index_url),
+                Some(Line::ExtraIndexUrl(index_url)) => self.extra_index_urls.push(index_url),
+                Some(Line::Requirement(requirement)) if constraint => {
+                    self.constraints.push(requirement)
+                }
+                Some(Line::Requirement(requirement)) => self.requirements.push(requirement),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn source(&self) -> Source {
+        match &self.index_url {
+            Some(index_url) => index_source(index_url),
+            None => Source::Default,
+        }
+    }
+}
+
+/// Join lines continued with a backslash, and remove comments.
+fn logical_lines(buffer: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for line in buffer.lines() {
+        match line.strip_suffix('\\') {
+            Some(continued) => current.push_str(continued),
+            None => {
+                current.push_str(line);
+                lines.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    lines.push(current);
+
+    lines
+        .into_iter()
+        .map(|line| {
+            // Comments start a line, or follow whitespace so that URL fragments are kept
+            let comment = line
+                .char_indices()
+                .find(|(index, character)| {
+                    *character == '#'
+                        && (*index == 0 || line[..*index].ends_with(char::is_whitespace))
+                })
+                .map(|(index, _)| index);
+            match comment {
+                Some(index) => line[..index].trim().to_owned(),
+                None => line.trim().to_owned(),
+            }
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Split an option from its value, as in `-r file`, `-rfile`, `--requirement file` or
+/// `--requirement=file`.
+fn split_option(line: &str) -> (&str, &str) {
+    if !line.starts_with("--") {
+        let split = line.char_indices().nth(2).map(|(index, _)| index);
+        let split = split.unwrap_or(line.len());
+        return (&line[..split], line[split..].trim());
+    }
+    match line.find(|character: char| character == '=' || character.is_whitespace()) {
+        Some(split) => (&line[..split], line[split + 1..].trim()),
+        None => (line, ""),
+    }
+}
+
+fn parse_line(line: &str) -> Option<Line> {
+    if line.starts_with('-') {
+        let (option, value) = split_option(line);
+        return match option {
+            "-r" | "--requirement" => Some(Line::Include(PathBuf::from(value))),
+            "-c" | "--constraint" => Some(Line::Constraint(PathBuf::from(value))),
+            "-i" | "--index-url" => Some(Line::IndexUrl(value.to_owned())),
+            "--extra-index-url" => Some(Line::ExtraIndexUrl(value.to_owned())),
+            // Editable installs are either local paths or VCS URLs
+            "-e" | "--editable" if value.contains("://") => {
+                parse_url_requirement(value).map(Line::Requirement)
+            }
+            "-e" | "--editable" => {
+                debug!("Skipping local python requirement '{}'", value);
+                None
+            }
+            _ => {
+                debug!("Ignoring requirements option '{}'", option);
+                None
+            }
+        };
+    }
+
+    // Options for a single requirement, such as `--hash`, follow it
+    let requirement = match line.find(" --") {
+        Some(index) => line[..index].trim(),
+        None => line,
+    };
+    if is_url(requirement) {
+        return parse_url_requirement(requirement).map(Line::Requirement);
+    }
+    let parsed = Requirement::parse(requirement);
+    if parsed.is_none() {
+        warn!("Skipping unsupported python requirement '{}'", requirement);
+    }
+    parsed.map(Line::Requirement)
+}
+
+/// Whether the requirement is a bare URL or path, rather than a named requirement.
+fn is_url(requirement: &str) -> bool {
+    let name_end = requirement
+        .find(|character: char| character.is_whitespace() || character == '@')
+        .unwrap_or(requirement.len());
+    requirement[..name_end].contains("://")
+        || requirement.starts_with('.')
+        || requirement.starts_with('/')
+}
+
+/// Parse a URL or path, which is named by its `#egg=` fragment if not local.
+fn parse_url_requirement(requirement: &str) -> Option<Requirement> {
+    if is_local(requirement) {
+        debug!("Skipping local python requirement '{}'", requirement);
+        return None;
+    }
+
+    let name = requirement.split('#').nth(1).and_then(|fragment| {
+        fragment
+            .split('&')
+            .find_map(|parameter| parameter.strip_prefix("egg="))
+    });
+    match name {
+        Some(name) => Some(Requirement {
+            name: name.to_owned(),
+            url: Some(requirement.to_owned()),
+            ..Requirement::default()
+        }),
+        None => {
+            warn!("Skipping unnamed python requirement '{}'", requirement);
+            None
+        }
+    }
+}
+
+fn is_local(requirement: &str) -> bool {
+    requirement.starts_with('.') || requirement.starts_with('/') || requirement.starts_with("file:")
+}
+
+impl DependencySpecs for Requirements {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        let pins: HashMap<String, &str> = self
+            .constraints
+            .iter()
+            .filter_map(|constraint| {
+                let version = constraint.pinned_version()?;
+                Some((normalize_name(&constraint.name), version))
+            })
+            .collect();
+        let source = self.source();
+
+        Box::new(self.requirements.iter().filter_map(move |requirement| {
+            let mut spec = requirement.to_spec(Scope::Normal)?;
+            let version = requirement
+                .pinned_version()
+                .or_else(|| pins.get(&normalize_name(&requirement.name)).copied());
+            if let Some(version) = version {
+                spec = spec.with_version(version);
+            }
+            if spec.source == Source::Default {
+                spec = spec.with_source(source.clone());
+            }
+            Some(spec)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn requirement(line: &str) -> Requirement {
+        match parse_line(line) {
+            Some(Line::Requirement(requirement)) => requirement,
+            _ => panic!("Not a requirement: {}", line),
+        }
+    }
+
+    #[test]
+    fn joins_continuations_and_removes_comments() {
+        let lines = logical_lines(
+            "# pinned\nrequests==2.31.0 \\\n    --hash=sha256:abc \\\n    --hash=sha256:def\n\n\
+             yalich @ git+https://github.com/org/yalich#egg=yalich  # from git\n",
+        );
+        assert_eq!(
+            lines,
+            [
+                "requests==2.31.0     --hash=sha256:abc     --hash=sha256:def",
+                "yalich @ git+https://github.com/org/yalich#egg=yalich",
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_hash_options() {
+        let requirement = requirement("requests==2.31.0 --hash=sha256:abc --hash=sha256:def");
+        assert_eq!(requirement.name, "requests");
+        assert_eq!(requirement.pinned_version(), Some("2.31.0"));
+    }
+
+    #[test]
+    fn parses_options() {
+        assert!(matches!(
+            parse_line("-r base.txt"),
+            Some(Line::Include(path)) if path == Path::new("base.txt")
+        ));
+        assert!(matches!(
+            parse_line("--requirement=base.txt"),
+            Some(Line::Include(path)) if path == Path::new("base.txt")
+        ));
+        assert!(matches!(
+            parse_line("-cconstraints.txt"),
+            Some(Line::Constraint(path)) if path == Path::new("constraints.txt")
+        ));
+        assert!(matches!(
+            parse_line("--index-url https://pypi.example.com/simple"),
+            Some(Line::IndexUrl(url)) if url == "https://pypi.example.com/simple"
+        ));
+        assert!(matches!(
+            parse_line("--extra-index-url=https://pypi.example.com/simple"),
+            Some(Line::ExtraIndexUrl(url)) if url == "https://pypi.example.com/simple"
+        ));
+        assert!(parse_line("--no-binary :all:").is_none());
+    }
+
+    #[test]
+    fn parses_editable_lines() {
+        let requirement = requirement("-e git+https://github.com/org/yalich@v1.0#egg=yalich");
+        assert_eq!(requirement.name, "yalich");
+        assert_eq!(
+            requirement.source(),
+            Some(Source::Git {
+                url: "https://github.com/org/yalich".to_owned(),
+                reference: Some("v1.0".to_owned()),
+            })
+        );
+
+        assert!(parse_line("-e .").is_none());
+        assert!(parse_line("--editable ./packages/local").is_none());
+        assert!(parse_line("-e git+https://github.com/org/yalich").is_none());
+    }
+
+    #[test]
+    fn parses_urls_and_paths() {
+        let requirement = requirement("https://example.com/yalich-1.0.tar.gz#egg=yalich");
+        assert_eq!(requirement.name, "yalich");
+        assert_eq!(
+            requirement.url.as_deref(),
+            Some("https://example.com/yalich-1.0.tar.gz#egg=yalich")
+        );
+
+        assert!(parse_line("https://example.com/yalich-1.0.tar.gz").is_none());
+        assert!(parse_line("./packages/local").is_none());
+        assert!(parse_line("/opt/packages/local.whl").is_none());
+        assert!(parse_line("file:///opt/packages/local.whl#egg=local").is_none());
+    }
+
+    #[test]
+    fn parses_markers() {
+        let requirement = requirement("pywin32>=300 ; sys_platform == \"win32\"");
+        assert_eq!(requirement.name, "pywin32");
+        assert_eq!(requirement.specifier.as_deref(), Some(">=300"));
+        assert_eq!(
+            requirement.marker.as_deref(),
+            Some("sys_platform == \"win32\"")
+        );
+    }
+
+    #[test]
+    fn loads_includes_and_constraints() {
+        let directory =
+            std::env::temp_dir().join(format!("yalich-requirements-{}", std::process::id()));
+        fs::create_dir_all(directory.join("nested")).unwrap();
+        fs::write(
+            directory.join("requirements.txt"),
+            "-r nested/base.txt\n-c constraints.txt\n--extra-index-url https://pypi.example.com/simple\nflask\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("nested/base.txt"),
+            "requests>=2\n-r ../requirements.txt\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("constraints.txt"),
+            "flask==3.0.0\nrequests==2.31.0\n",
+        )
+        .unwrap();
+
+        let requirements = Requirements::load(&directory.join("requirements.txt")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            requirements.extra_index_urls,
+            ["https://pypi.example.com/simple"]
+        );
+        let specs: Vec<_> = requirements
+            .dependency_specs()
+            .map(|spec| (spec.name, spec.version, spec.requirement))
+            .collect();
+        assert_eq!(
+            specs,
+            [
+                (
+                    "requests".to_owned(),
+                    Some("2.31.0".to_owned()),
+                    Some(">=2".to_owned())
+                ),
+                ("flask".to_owned(), Some("3.0.0".to_owned()), None),
+            ]
+        );
+    }
+}
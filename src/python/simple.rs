@@ -0,0 +1,401 @@
+use std::env;
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde_derive::Deserialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::core::Registry;
+use crate::python::distribution::Distribution;
+use crate::python::metadata::CoreMetadata;
+use crate::python::pep440::Version;
+use crate::python::pep508::normalize_name;
+
+const SIMPLE_JSON: &str = "application/vnd.pypi.simple.v1+json";
+
+/// A distribution file of a project, as listed by the index.
+#[derive(Debug, Deserialize)]
+pub struct ProjectFile {
+    pub filename: String,
+    pub url: String,
+    /// Whether the metadata is served alongside the file, as defined by PEP 658.
+    #[serde(default, rename = "core-metadata")]
+    pub core_metadata: Option<Value>,
+    #[serde(default, rename = "dist-info-metadata")]
+    pub dist_info_metadata: Option<Value>,
+    #[serde(default)]
+    pub yanked: Option<Value>,
+}
+
+impl ProjectFile {
+    fn is_set(value: &Option<Value>) -> bool {
+        !matches!(value, None | Some(Value::Null) | Some(Value::Bool(false)))
+    }
+
+    fn has_metadata(&self) -> bool {
+        Self::is_set(&self.core_metadata) || Self::is_set(&self.dist_info_metadata)
+    }
+
+    fn is_yanked(&self) -> bool {
+        Self::is_set(&self.yanked)
+    }
+
+    fn is_wheel(&self) -> bool {
+        self.filename.ends_with(".whl")
+    }
+
+    /// Version from the filename, as in `<name>-<version>-<tags>.whl`.
+    fn version(&self) -> Option<&str> {
+        if self.is_wheel() {
+            return self.filename.split('-').nth(1);
+        }
+        let stem = [".tar.gz", ".zip", ".tar.bz2"]
+            .iter()
+            .find_map(|extension| self.filename.strip_suffix(extension))?;
+        stem.rsplit_once('-').map(|(_name, version)| version)
+    }
+}
+
+/// A project page of the Simple API, as defined by PEP 691.
+#[derive(Debug, Deserialize)]
+pub struct Project {
+    pub files: Vec<ProjectFile>,
+}
+
+/// Metadata of a release, read from the files on the index.
+pub struct IndexPackage {
+    pub url: String,
+    /// The version read, which is the latest if none was given.
+    pub version: String,
+    pub distribution: Distribution,
+}
+
+/// A Python package index serving the Simple API, such as a devpi or Artifactory mirror.
+pub struct SimpleIndex<'a> {
+    client: &'a Client,
+    name: String,
+    index: Url,
+    username: Option<String>,
+    token: Option<String>,
+}
+
+impl<'a> SimpleIndex<'a> {
+    pub fn new(client: &'a Client, name: &str, registry: &Registry) -> Result<Self> {
+        let mut index = Url::parse(&format!("{}/", registry.index.trim_end_matches('/')))
+            .with_context(|| format!("Invalid index URL for python index '{}'.", name))?;
+
+        // Credentials may be given in the URL, as for pip
+        let mut username = registry.username.to_owned();
+        let mut token = registry.token.to_owned();
+        if !index.username().is_empty() {
+            username = Some(index.username().to_owned());
+            token = index.password().map(ToOwned::to_owned);
+            index
+                .set_username("")
+                .and_then(|_| index.set_password(None))
+                .map_err(|_| anyhow!("Invalid index URL for python index '{}'.", name))?;
+        }
+
+        // Poetry reads index credentials from the environment in the same way
+        let variable = |suffix| {
+            env::var(format!(
+                "POETRY_HTTP_BASIC_{}_{}",
+                name.to_uppercase().replace('-', "_"),
+                suffix
+            ))
+            .ok()
+        };
+        let username = username.or_else(|| variable("USERNAME"));
+        let token = token.or_else(|| variable("PASSWORD"));
+
+        Ok(Self {
+            client,
+            name: name.to_owned(),
+            index,
+            username,
+            token,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether this is the index at the given URL, ignoring any credentials in it.
+    pub fn is_index(&self, index: &str) -> bool {
+        let index = match Url::parse(index) {
+            Ok(mut index) => {
+                let _ = index.set_username("");
+                let _ = index.set_password(None);
+                index.to_string()
+            }
+            Err(_) => return false,
+        };
+        self.index.as_str().trim_end_matches('/') == index.trim_end_matches('/')
+    }
+
+    /// The project page listing every file of a package.
+    pub fn url(&self, package_name: &str) -> String {
+        format!("{}{}/", self.index, normalize_name(package_name))
+    }
+
+    fn get(&self, url: Url) -> RequestBuilder {
+        // Don't send credentials to other hosts, such as a CDN serving the files
+        let same_host = url.host_str() == self.index.host_str();
+        let request = self.client.get(url);
+        match (&self.username, &self.token) {
+            (Some(username), password) if same_host => {
+                request.basic_auth(username, password.as_ref())
+            }
+            (None, Some(token)) if same_host => request.bearer_auth(token),
+            _ => request,
+        }
+    }
+
+    /// List the files of a package, or `None` if the index doesn't have it.
+    pub fn project(&self, package_name: &str) -> Result<Option<Project>> {
+        let url = Url::parse(&self.url(package_name))
+            .with_context(|| format!("Invalid URL for python package '{}'.", package_name))?;
+        let response = self
+            .get(url.clone())
+            .header(ACCEPT, format!("{}, text/html;q=0.1", SIMPLE_JSON))
+            .send()
+            .with_context(|| format!("Index request for '{}' failed.", package_name))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let json = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map(|content_type| content_type.contains("json"))
+            .unwrap_or(false);
+
+        let mut project: Project = if json {
+            response
+                .json()
+                .with_context(|| format!("JSON deserialization for '{}' failed.", package_name))?
+        } else {
+            parse_html(&response.text()?)
+        };
+        // File URLs may be relative to the project page
+        for file in &mut project.files {
+            if let Ok(file_url) = url.join(&file.url) {
+                file.url = file_url.to_string();
+            }
+        }
+        Ok(Some(project))
+    }
+
+    /// Read the metadata of a file, from alongside it if served and it names a license,
+    /// or otherwise from inside the file.
+    fn distribution(&self, file: &ProjectFile) -> Result<Option<Distribution>> {
+        let mut url = Url::parse(&file.url)
+            .with_context(|| format!("Invalid URL for python file '{}'.", file.filename))?;
+        url.set_fragment(None);
+
+        if file.has_metadata() {
+            let metadata_url = Url::parse(&format!("{}.metadata", url))?;
+            let metadata = self
+                .get(metadata_url)
+                .send()
+                .and_then(|response| response.error_for_status())
+                .with_context(|| format!("Metadata request for '{}' failed.", file.filename))?
+                .text()?;
+            let metadata = CoreMetadata::parse(&metadata);
+            if metadata.license().is_some() {
+                return Ok(Some(Distribution {
+                    metadata,
+                    license_files: Vec::new(),
+                }));
+            }
+        }
+
+        debug!("Downloading {} for its metadata", file.filename);
+        let bytes = self
+            .get(url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Download of '{}' failed.", file.filename))?
+            .bytes()?;
+        Distribution::read(&file.filename, &bytes)
+    }
+
+    /// Fetch the metadata of the given version, or the latest, or `None` if not found.
+    pub fn fetch_package(
+        &self,
+        package_name: &str,
+        version: Option<&str>,
+    ) -> Result<Option<IndexPackage>> {
+        let project = match self.project(package_name)? {
+            Some(project) => project,
+            None => return Ok(None),
+        };
+        let version = match version {
+            Some(version) => version.to_owned(),
+            None => match latest_version(&project) {
+                Some(version) => version.to_owned(),
+                None => return Ok(None),
+            },
+        };
+
+        // Wheels are preferred, as their metadata is reliable
+        let parsed = Version::parse(&version);
+        let mut files: Vec<&ProjectFile> = project
+            .files
+            .iter()
+            .filter(|file| match (file.version(), &parsed) {
+                (Some(other), Some(parsed)) => Version::parse(other).as_ref() == Some(parsed),
+                (Some(other), None) => other == version,
+                (None, _) => false,
+            })
+            .collect();
+        files.sort_by_key(|file| !file.is_wheel());
+        for file in files {
+            match self.distribution(file) {
+                Ok(Some(distribution)) => {
+                    return Ok(Some(IndexPackage {
+                        url: self.url(package_name),
+                        version,
+                        distribution,
+                    }))
+                }
+                Ok(None) => {}
+                Err(error) => warn!(
+                    "Failed to read metadata of python package '{}' from {}: {:#}",
+                    package_name, file.filename, error
+                ),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The newest final release, or the newest pre-release if there are no others.
+fn latest_version(project: &Project) -> Option<&str> {
+    project
+        .files
+        .iter()
+        .filter(|file| !file.is_yanked())
+        .filter_map(|file| {
+            let version = file.version()?;
+            Some((Version::parse(version)?, version))
+        })
+        .max_by(|(version, _), (other, _)| {
+            (!version.is_prerelease(), version).cmp(&(!other.is_prerelease(), other))
+        })
+        .map(|(_, version)| version)
+}
+
+/// Read an attribute value from an HTML tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{}=", name))? + name.len() + 1;
+    let rest = &tag[start..];
+    let quote = rest
+        .chars()
+        .next()
+        .filter(|quote| *quote == '"' || *quote == '\'')?;
+    let rest = &rest[1..];
+    rest.find(quote).map(|end| &rest[..end])
+}
+
+/// Parse a project page of the HTML Simple API, as defined by PEP 503.
+fn parse_html(html: &str) -> Project {
+    let mut files = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("<a ") {
+        rest = &rest[start..];
+        let tag_end = match rest.find('>') {
+            Some(tag_end) => tag_end,
+            None => break,
+        };
+        let tag = &rest[..tag_end];
+        let text_end = rest.find("</a>").unwrap_or(rest.len());
+        let filename = rest[tag_end + 1..text_end.max(tag_end + 1)].trim();
+        if let Some(href) = attribute(tag, "href") {
+            let flag = |name| attribute(tag, name).map(|value| Value::String(value.to_owned()));
+            files.push(ProjectFile {
+                filename: filename.to_owned(),
+                url: href.replace("&amp;", "&"),
+                core_metadata: flag("data-core-metadata"),
+                dist_info_metadata: flag("data-dist-info-metadata"),
+                yanked: flag("data-yanked"),
+            });
+        }
+        rest = &rest[tag_end..];
+    }
+    Project { files }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_html_links() {
+        let project = parse_html(
+            r#"<!DOCTYPE html>
+<html><body>
+<a href="../../files/foo-1.0.tar.gz#sha256=abc">foo-1.0.tar.gz</a>
+<a href='https://files.example.com/foo-1.1-py3-none-any.whl?a=1&amp;b=2' data-dist-info-metadata="sha256=def" data-yanked="">foo-1.1-py3-none-any.whl</a>
+<a name="anchor">not a file</a>
+</body></html>"#,
+        );
+        let files: Vec<_> = project
+            .files
+            .iter()
+            .map(|file| (file.filename.as_str(), file.url.as_str()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("foo-1.0.tar.gz", "../../files/foo-1.0.tar.gz#sha256=abc"),
+                (
+                    "foo-1.1-py3-none-any.whl",
+                    "https://files.example.com/foo-1.1-py3-none-any.whl?a=1&b=2"
+                ),
+            ]
+        );
+        assert!(!project.files[0].has_metadata());
+        assert!(project.files[1].has_metadata());
+        assert!(project.files[1].is_yanked());
+    }
+
+    #[test]
+    fn reads_versions_from_filenames() {
+        let project: Project = serde_json::from_str(
+            r#"{"files": [
+                {"filename": "foo-1.0-py3-none-any.whl", "url": "a"},
+                {"filename": "foo-bar-1.1.tar.gz", "url": "b"},
+                {"filename": "foo-2.0rc1.zip", "url": "c"},
+                {"filename": "foo-1.2.tar.gz", "url": "d", "yanked": "broken"},
+                {"filename": "foo-1.1.exe", "url": "e"}
+            ]}"#,
+        )
+        .unwrap();
+        let versions: Vec<_> = project.files.iter().map(ProjectFile::version).collect();
+        assert_eq!(
+            versions,
+            [Some("1.0"), Some("1.1"), Some("2.0rc1"), Some("1.2"), None]
+        );
+        assert_eq!(latest_version(&project), Some("1.1"));
+    }
+
+    #[test]
+    fn prefers_prereleases_to_nothing() {
+        let project: Project = serde_json::from_str(
+            r#"{"files": [
+                {"filename": "foo-2.0b1.tar.gz", "url": "a"},
+                {"filename": "foo-2.0rc1.tar.gz", "url": "b", "core-metadata": false}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(!project.files[1].has_metadata());
+        assert_eq!(latest_version(&project), Some("2.0rc1"));
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{BTreeMap, HashMap};
+
+use log::warn;
+use serde_derive::Deserialize;
+
+use crate::core::{walk_scopes, DependencySpec, DependencySpecs, Scope, Source};
+use crate::python::index_source;
+
+#[derive(Deserialize)]
+pub struct PackageReference {
+    pub name: String,
+}
+
+/// Where a locked package was installed from, as one of these keys.
+#[derive(Deserialize, Default)]
+pub struct PackageSource {
+    #[serde(default)]
+    pub registry: Option<String>,
+    /// Like `https://github.com/org/repo?rev=main#<commit>`.
+    #[serde(default)]
+    pub git: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub directory: Option<String>,
+    #[serde(default)]
+    pub editable: Option<String>,
+    #[serde(default)]
+    pub r#virtual: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub source: PackageSource,
+    #[serde(default)]
+    pub dependencies: Vec<PackageReference>,
+    #[serde(default, rename = "optional-dependencies")]
+    pub optional_dependencies: BTreeMap<String, Vec<PackageReference>>,
+    #[serde(default, rename = "dev-dependencies")]
+    pub dev_dependencies: BTreeMap<String, Vec<PackageReference>>,
+}
+
+impl Package {
+    /// Whether this is a project in the workspace, rather than a dependency of one.
+    fn is_member(&self) -> bool {
+        self.source.editable.is_some() || self.source.r#virtual.is_some()
+    }
+
+    /// Where this package was installed from, or `None` for local paths and URLs.
+    fn source(&self) -> Option<Source> {
+        let source = &self.source;
+        if let Some(registry) = &source.registry {
+            return Some(index_source(registry));
+        }
+        if let Some(git) = &source.git {
+            let (url, commit) = match git.split_once('#') {
+                Some((url, commit)) => (url, Some(commit)),
+                None => (git.as_str(), None),
+            };
+            let (url, query) = match url.split_once('?') {
+                Some((url, query)) => (url, Some(query)),
+                None => (url, None),
+            };
+            let reference = commit.or_else(|| {
+                query?
+                    .split('&')
+                    .find_map(|parameter| parameter.split_once('=').map(|(_, value)| value))
+            });
+            return Some(Source::Git {
+                url: url.to_owned(),
+                reference: reference.map(ToOwned::to_owned),
+            });
+        }
+        if let Some(url) = &source.url {
+            warn!(
+                "Skipping python package '{}' installed from URL {}",
+                self.name, url
+            );
+        }
+        None
+    }
+}
+
+/// uv's lockfile, pinning every package in the workspace.
+#[derive(Deserialize)]
+pub struct UvLock {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<Package>,
+}
+
+impl UvLock {
+    /// Walk the locked graph from the workspace members, finding the scope of each package.
+    ///
+    /// Anything pulled in by an extra or a development group shares its scope.
+    fn package_scopes(&self) -> HashMap<&str, Scope> {
+        let mut packages: HashMap<&str, Vec<&Package>> = HashMap::new();
+        for package in &self.packages {
+            packages.entry(&package.name).or_default().push(package);
+        }
+
+        let roots = self
+            .packages
+            .iter()
+            .filter(|package| package.is_member())
+            .flat_map(|member| {
+                member
+                    .dependencies
+                    .iter()
+                    .map(|reference| (reference, Scope::Normal))
+                    .chain(
+                        member
+                            .optional_dependencies
+                            .values()
+                            .flatten()
+                            .map(|reference| (reference, Scope::Optional)),
+                    )
+                    .chain(
+                        member
+                            .dev_dependencies
+                            .values()
+                            .flatten()
+                            .map(|reference| (reference, Scope::Dev)),
+                    )
+            })
+            .map(|(reference, scope)| (reference.name.as_str(), scope));
+        walk_scopes(roots, |name, scope| {
+            packages
+                .get(name)
+                .into_iter()
+                .flatten()
+                .flat_map(|package| {
+                    package
+                        .dependencies
+                        .iter()
+                        .chain(package.optional_dependencies.values().flatten())
+                })
+                .map(move |reference| (reference.name.as_str(), scope))
+        })
+    }
+}
+
+impl DependencySpecs for UvLock {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        let scopes = self.package_scopes();
+        let specs: Vec<_> = self
+            .packages
+            .iter()
+            .filter(|package| !package.is_member())
+            .filter_map(|package| {
+                let mut spec = DependencySpec::new(&package.name)
+                    .with_scope(
+                        scopes
+                            .get(package.name.as_str())
+                            .copied()
+                            .unwrap_or_default(),
+                    )
+                    .with_source(package.source()?);
+                if let Some(version) = &package.version {
+                    spec = spec.with_version(version);
+                }
+                Some(spec)
+            })
+            .collect();
+        Box::new(specs.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UV_LOCK: &str = r#"
+version = 1
+requires-python = ">=3.8"
+
+[[package]]
+name = "app"
+version = "0.1.0"
+source = { editable = "." }
+dependencies = [{ name = "requests" }]
+
+[package.optional-dependencies]
+socks = [{ name = "pysocks" }]
+
+[package.dev-dependencies]
+test = [{ name = "pytest" }]
+
+[[package]]
+name = "requests"
+version = "2.31.0"
+source = { registry = "https://pypi.org/simple" }
+dependencies = [{ name = "urllib3" }]
+
+[[package]]
+name = "urllib3"
+version = "2.0.4"
+source = { registry = "https://pypi.org/simple" }
+
+[[package]]
+name = "pysocks"
+version = "1.7.1"
+source = { registry = "https://pypi.example.com/simple" }
+
+[[package]]
+name = "pytest"
+version = "7.4.0"
+source = { git = "https://github.com/pytest-dev/pytest?tag=7.4.0#0123abcd" }
+dependencies = [{ name = "requests" }, { name = "local" }]
+
+[[package]]
+name = "local"
+version = "0.1.0"
+source = { directory = "../local" }
+"#;
+
+    #[test]
+    fn walks_scopes_from_members() {
+        let uv_lock: UvLock = toml::from_str(UV_LOCK).unwrap();
+        let specs: Vec<_> = uv_lock.dependency_specs().collect();
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("requests").with_version("2.31.0"),
+                DependencySpec::new("urllib3").with_version("2.0.4"),
+                DependencySpec::new("pysocks")
+                    .with_version("1.7.1")
+                    .with_scope(Scope::Optional)
+                    .with_source(Source::Registry(
+                        "https://pypi.example.com/simple".to_owned()
+                    )),
+                DependencySpec::new("pytest")
+                    .with_version("7.4.0")
+                    .with_scope(Scope::Dev)
+                    .with_source(Source::Git {
+                        url: "https://github.com/pytest-dev/pytest".to_owned(),
+                        reference: Some("0123abcd".to_owned()),
+                    }),
+            ]
+        );
+    }
+}
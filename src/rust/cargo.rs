@@ -1,26 +1,691 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
 use serde_derive::Deserialize;
+use toml::value::{Table, Value};
+
+use crate::core::{load_toml_file, DependencySpec, DependencySpecs, RustLanguage, Scope, Source};
+
+const MANIFEST_NAME: &str = "Cargo.toml";
+
+#[derive(Deserialize, Default)]
+pub struct Workspace {
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub dependencies: Table,
+}
+
+/// The `[package]` table of a published crate, whose manifest has been normalized by Cargo.
+#[derive(Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub license: Option<String>,
+    #[serde(default, rename = "license-file")]
+    pub license_file: Option<String>,
+    #[serde(default)]
+    pub homepage: Option<String>,
+    #[serde(default)]
+    pub repository: Option<String>,
+}
 
-use crate::core::DependencyNames;
+#[derive(Deserialize)]
+pub struct PublishedCargo {
+    pub package: Package,
+}
+
+/// Dependency tables, as found at the top level or under a `[target.'cfg(..)']` table.
+#[derive(Deserialize, Default)]
+pub struct DependencyTables {
+    #[serde(default)]
+    pub dependencies: Table,
+    #[serde(default, rename = "dev-dependencies", alias = "dev_dependencies")]
+    pub dev_dependencies: Table,
+    #[serde(default, rename = "build-dependencies", alias = "build_dependencies")]
+    pub build_dependencies: Table,
+}
 
 #[derive(Deserialize)]
 pub struct Cargo {
-    pub dependencies: toml::value::Table,
+    #[serde(default)]
+    pub package: Option<Table>,
+    #[serde(flatten)]
+    pub tables: DependencyTables,
+    #[serde(default)]
+    pub target: BTreeMap<String, DependencyTables>,
+    #[serde(default)]
+    pub workspace: Option<Workspace>,
+    #[serde(default)]
+    pub features: BTreeMap<String, Vec<String>>,
+    /// Workspace members, if this is a workspace root.
+    #[serde(skip)]
+    pub members: Vec<Cargo>,
+    /// `[workspace.dependencies]` of the workspace this crate belongs to.
+    #[serde(skip)]
+    pub inherited: Table,
+    /// Optional dependencies activated by the enabled features, or `None` if all are.
+    #[serde(skip)]
+    pub activated: Option<HashSet<String>>,
+    /// Features enabled on this crate.
+    #[serde(skip)]
+    pub enabled: HashSet<String>,
+    /// Features enabled on dependencies, by `<crate>/<feature>` values.
+    #[serde(skip)]
+    pub dependency_features: HashSet<(String, String)>,
 }
 
-impl DependencyNames for Cargo {
-    fn dependency_names<'a>(&'a self) -> Box<dyn Iterator<Item = &'a str> + 'a> {
-        Box::new(
-            self.dependencies
-                .iter()
-                .filter(|(_crate_name, crate_spec)| match crate_spec {
-                    toml::value::Value::String(_) => true,
-                    // Filter out local dependencies with the path property
-                    toml::value::Value::Table(crate_spec_table) => {
-                        crate_spec_table.get("path").is_none()
+impl Cargo {
+    /// Load a manifest, along with its workspace members or the workspace it belongs to.
+    pub fn load(path: &Path, language: &RustLanguage) -> Result<Self> {
+        let mut cargo: Cargo = load_toml_file(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        if let Some(workspace) = &cargo.workspace {
+            let mut members = Vec::new();
+            for member_path in workspace.member_paths(directory)? {
+                debug!("Loading workspace member {}", member_path.display());
+                let mut member: Cargo = load_toml_file(&member_path)?;
+                member.inherited = workspace.dependencies.clone();
+                members.push(member);
+            }
+            cargo.inherited = workspace.dependencies.clone();
+
+            let mut crates = vec![cargo];
+            crates.extend(members);
+            // Configured features select the root package, or every member of a virtual
+            // workspace, and other members are built with their default features
+            let virtual_workspace = crates[0].package.is_none();
+            for (index, cargo) in crates.iter_mut().enumerate() {
+                if index == 0 || virtual_workspace {
+                    cargo.activate(language.all_features, configured_features(language));
+                } else {
+                    cargo.activate(language.all_features, vec!["default".to_owned()]);
+                }
+            }
+            unify_features(&mut crates);
+            let mut cargo = crates.remove(0);
+            cargo.members = crates;
+            return Ok(cargo);
+        } else if let Some(workspace) = find_workspace(directory)? {
+            cargo.inherited = workspace.dependencies;
+        }
+
+        cargo.activate(language.all_features, configured_features(language));
+        Ok(cargo)
+    }
+
+    /// This crate and its workspace members.
+    pub fn crates(&self) -> impl Iterator<Item = &Cargo> {
+        std::iter::once(self).chain(&self.members)
+    }
+
+    pub fn package_name(&self) -> Option<&str> {
+        self.package.as_ref()?.get("name")?.as_str()
+    }
+
+    /// Enable features, and find the optional dependencies they activate.
+    fn activate(&mut self, all_features: bool, features: Vec<String>) {
+        if all_features {
+            self.activated = None;
+            return;
+        }
+
+        self.activated = Some(HashSet::new());
+        self.enable(features);
+    }
+
+    /// Enable features, along with the features and optional dependencies they name.
+    ///
+    /// Returns whether any feature was not already enabled.
+    fn enable(&mut self, mut queue: Vec<String>) -> bool {
+        let activated = match &mut self.activated {
+            Some(activated) => activated,
+            None => return false,
+        };
+        let mut changed = false;
+        while let Some(value) = queue.pop() {
+            if let Some(crate_name) = value.strip_prefix("dep:") {
+                activated.insert(crate_name.to_owned());
+            } else if let Some((crate_name, feature)) = value.split_once('/') {
+                // `crate?/feature` only enables the feature if the crate is already active
+                if !crate_name.ends_with('?') {
+                    activated.insert(crate_name.to_owned());
+                }
+                self.dependency_features.insert((
+                    crate_name.trim_end_matches('?').to_owned(),
+                    feature.to_owned(),
+                ));
+            } else if !self.enabled.contains(&value) {
+                changed = true;
+                match self.features.get(&value) {
+                    Some(values) => queue.extend(values.iter().cloned()),
+                    // Optional dependencies have an implicit feature of the same name
+                    None => {
+                        activated.insert(value.to_owned());
                     }
-                    _ => false,
+                }
+                self.enabled.insert(value);
+            }
+        }
+        changed
+    }
+
+    /// Whether a dependency is built, as it is either required or activated by a feature.
+    fn is_active(&self, crate_name: &str, crate_spec: &Value) -> bool {
+        let optional = crate_spec.get("optional") == Some(&Value::Boolean(true));
+        match &self.activated {
+            Some(activated) if optional => activated.contains(crate_name),
+            _ => true,
+        }
+    }
+
+    /// The active dependencies in every table, with the package name each is published under.
+    fn active_dependencies<'a>(
+        &'a self,
+        include_dev: bool,
+    ) -> impl Iterator<Item = (&'a str, Value)> + 'a {
+        let tables = std::iter::once(&self.tables).chain(self.target.values());
+        tables
+            .flat_map(move |tables| {
+                let dev_dependencies = if include_dev {
+                    Some(&tables.dev_dependencies)
+                } else {
+                    None
+                };
+                tables
+                    .dependencies
+                    .iter()
+                    .chain(&tables.build_dependencies)
+                    .chain(dev_dependencies.into_iter().flatten())
+            })
+            .map(move |(crate_name, crate_spec)| {
+                (
+                    crate_name.as_str(),
+                    inherit(crate_name, crate_spec, &self.inherited),
+                )
+            })
+            .filter(move |(crate_name, crate_spec)| self.is_active(crate_name, crate_spec))
+    }
+
+    /// The packages this crate depends on, leaving out optional dependencies no enabled
+    /// feature activates.
+    pub fn active_packages(&self) -> HashSet<String> {
+        self.active_dependencies(true)
+            .map(|(crate_name, crate_spec)| {
+                crate_spec
+                    .get("package")
+                    .and_then(Value::as_str)
+                    .unwrap_or(crate_name)
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    /// The features this crate enables on each of its active dependencies on disk.
+    ///
+    /// Dev dependencies are left out, as they aren't built for dependants.
+    fn path_dependency_features(&self) -> Vec<(String, Vec<String>)> {
+        let mut path_dependencies = Vec::new();
+        for (crate_name, crate_spec) in self.active_dependencies(false) {
+            if crate_spec.get("path").is_none() {
+                continue;
+            }
+            let mut features: Vec<String> = crate_spec
+                .get("features")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(ToOwned::to_owned)
+                .collect();
+            let default_features = crate_spec
+                .get("default-features")
+                .or_else(|| crate_spec.get("default_features"));
+            if default_features != Some(&Value::Boolean(false)) {
+                features.push("default".to_owned());
+            }
+            features.extend(
+                self.dependency_features
+                    .iter()
+                    .filter(|(dependency, _feature)| dependency == crate_name)
+                    .map(|(_dependency, feature)| feature.to_owned()),
+            );
+            let package = crate_spec
+                .get("package")
+                .and_then(Value::as_str)
+                .unwrap_or(crate_name);
+            path_dependencies.push((package.to_owned(), features));
+        }
+        path_dependencies
+    }
+
+    fn crate_specs<'a>(
+        &'a self,
+        dependencies: &'a Table,
+        scope: Scope,
+    ) -> impl Iterator<Item = DependencySpec> + 'a {
+        dependencies
+            .iter()
+            .map(move |(crate_name, crate_spec)| {
+                (crate_name, inherit(crate_name, crate_spec, &self.inherited))
+            })
+            .filter(|(_crate_name, crate_spec)| match crate_spec {
+                Value::String(_) => true,
+                // Filter out local dependencies with the path property
+                Value::Table(crate_spec_table) => crate_spec_table.get("path").is_none(),
+                _ => false,
+            })
+            // Filter out optional dependencies that no enabled feature activates
+            .filter(move |(crate_name, crate_spec)| self.is_active(crate_name, crate_spec))
+            .map(move |(crate_name, crate_spec)| crate_spec_to_spec(crate_name, &crate_spec, scope))
+    }
+}
+
+impl Workspace {
+    /// Expand member globs relative to the workspace root, dropping excluded paths.
+    fn member_paths(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        let excluded: Vec<PathBuf> = self
+            .exclude
+            .iter()
+            .map(|exclude| root.join(exclude))
+            .collect();
+
+        let mut member_paths = Vec::new();
+        for member in &self.members {
+            let pattern = root.join(member);
+            let pattern = pattern.to_string_lossy();
+            let directories = glob::glob(&pattern)
+                .with_context(|| format!("Invalid workspace member glob '{}'", member))?;
+            for directory in directories {
+                let directory = directory?;
+                // The root package is already loaded, and may list itself as a member
+                if directory == root || directory == Path::new(".") {
+                    continue;
+                }
+                if excluded
+                    .iter()
+                    .any(|excluded| directory.starts_with(excluded))
+                {
+                    continue;
+                }
+                let member_path = directory.join(MANIFEST_NAME);
+                if member_path.is_file() {
+                    member_paths.push(member_path);
+                }
+            }
+        }
+        member_paths.sort();
+        Ok(member_paths)
+    }
+}
+
+/// The features enabled on the selected packages by the configuration, as with `cargo build`.
+fn configured_features(language: &RustLanguage) -> Vec<String> {
+    let mut features = language.features.clone();
+    if !language.no_default_features {
+        features.push("default".to_owned());
+    }
+    features
+}
+
+/// Enable the features that workspace members enable on each other, as Cargo unifies them.
+///
+/// A dependant enables a member's default features unless it sets `default-features = false`.
+fn unify_features(crates: &mut [Cargo]) {
+    let indices: HashMap<String, usize> = crates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, cargo)| Some((cargo.package_name()?.to_owned(), index)))
+        .collect();
+    loop {
+        let requests: Vec<(usize, Vec<String>)> = crates
+            .iter()
+            .flat_map(Cargo::path_dependency_features)
+            .filter_map(|(package, features)| Some((*indices.get(&package)?, features)))
+            .collect();
+        let mut changed = false;
+        for (index, features) in requests {
+            changed |= crates[index].enable(features);
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Search upwards from a member crate for the workspace root it belongs to.
+fn find_workspace(directory: &Path) -> Result<Option<Workspace>> {
+    for ancestor in directory.ancestors().skip(1) {
+        let manifest_path = ancestor.join(MANIFEST_NAME);
+        if manifest_path.is_file() {
+            let root: Cargo = load_toml_file(&manifest_path)?;
+            if root.workspace.is_some() {
+                return Ok(root.workspace);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Replace `{ workspace = true }` with the workspace entry, keeping any keys set by the member.
+fn inherit(crate_name: &str, crate_spec: &Value, inherited: &Table) -> Value {
+    let crate_spec_table = match crate_spec {
+        Value::Table(crate_spec_table)
+            if crate_spec_table.get("workspace") == Some(&Value::Boolean(true)) =>
+        {
+            crate_spec_table
+        }
+        _ => return crate_spec.to_owned(),
+    };
+
+    let mut table = match inherited.get(crate_name) {
+        Some(Value::String(version)) => {
+            let mut table = Table::new();
+            table.insert("version".to_owned(), Value::String(version.to_owned()));
+            table
+        }
+        Some(Value::Table(table)) => table.to_owned(),
+        _ => {
+            warn!("No workspace dependency found for '{}'", crate_name);
+            return crate_spec.to_owned();
+        }
+    };
+    for (key, value) in crate_spec_table {
+        if key != "workspace" {
+            table.insert(key.to_owned(), value.to_owned());
+        }
+    }
+    Value::Table(table)
+}
+
+fn crate_spec_to_spec(crate_name: &str, crate_spec: &Value, scope: Scope) -> DependencySpec {
+    let optional = crate_spec.get("optional") == Some(&Value::Boolean(true));
+    let scope = match scope {
+        Scope::Normal if optional => Scope::Optional,
+        scope => scope,
+    };
+    // Renamed crates are published under the package key
+    let mut spec = match crate_spec.get("package").and_then(Value::as_str) {
+        Some(package) => DependencySpec::new(package).with_alias(crate_name),
+        None => DependencySpec::new(crate_name),
+    }
+    .with_scope(scope);
+    let requirement = match &crate_spec {
+        Value::String(requirement) => Some(requirement.as_str()),
+        _ => crate_spec.get("version").and_then(Value::as_str),
+    };
+    if let Some(requirement) = requirement {
+        spec = spec.with_requirement(requirement);
+    }
+    let registry = crate_spec
+        .get("registry")
+        .or_else(|| crate_spec.get("registry-index"))
+        .and_then(Value::as_str);
+    if let Some(registry) = registry {
+        spec = spec.with_source(Source::Registry(registry.to_owned()));
+    }
+    if let Some(url) = crate_spec.get("git").and_then(Value::as_str) {
+        let reference = ["rev", "tag", "branch"]
+            .iter()
+            .find_map(|key| crate_spec.get(key).and_then(Value::as_str));
+        spec = spec.with_source(Source::Git {
+            url: url.to_owned(),
+            reference: reference.map(ToOwned::to_owned),
+        });
+    }
+    spec
+}
+
+impl DependencyTables {
+    fn dependency_specs<'a>(
+        &'a self,
+        cargo: &'a Cargo,
+    ) -> impl Iterator<Item = DependencySpec> + 'a {
+        cargo
+            .crate_specs(&self.dependencies, Scope::Normal)
+            .chain(cargo.crate_specs(&self.dev_dependencies, Scope::Dev))
+            .chain(cargo.crate_specs(&self.build_dependencies, Scope::Build))
+    }
+}
+
+impl DependencySpecs for Cargo {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(
+            self.tables
+                .dependency_specs(self)
+                // Target specific dependencies keep the scope of their table
+                .chain(
+                    self.target
+                        .values()
+                        .flat_map(move |tables| tables.dependency_specs(self)),
+                )
+                .chain(
+                    self.members
+                        .iter()
+                        .flat_map(|member| member.dependency_specs()),
+                ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+serde = "1.0"
+flate2 = { version = "1.0", optional = true }
+local = { path = "../local" }
+
+[dev-dependencies]
+tempfile = "3.0"
+
+[build-dependencies]
+cc = "1.0"
+
+[target.'cfg(windows)'.dependencies]
+winapi = "0.3"
+
+[target.'cfg(unix)'.dev-dependencies]
+nix = "0.20"
+"#;
+
+    fn scopes(cargo: &Cargo) -> Vec<(String, Scope)> {
+        let mut scopes: Vec<_> = cargo
+            .dependency_specs()
+            .map(|spec| (spec.name, spec.scope))
+            .collect();
+        scopes.sort();
+        scopes
+    }
+
+    #[test]
+    fn scopes_dependency_tables() {
+        let cargo: Cargo = toml::from_str(MANIFEST).unwrap();
+        assert_eq!(
+            scopes(&cargo),
+            [
+                ("cc".to_owned(), Scope::Build),
+                ("flate2".to_owned(), Scope::Optional),
+                ("nix".to_owned(), Scope::Dev),
+                ("serde".to_owned(), Scope::Normal),
+                ("tempfile".to_owned(), Scope::Dev),
+                ("winapi".to_owned(), Scope::Normal),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_requirements_and_sources() {
+        let crate_spec: Value = toml::from_str(
+            r#"
+package = "serde_json"
+version = "1.0"
+registry = "private"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            crate_spec_to_spec("json", &crate_spec, Scope::Normal),
+            DependencySpec::new("serde_json")
+                .with_alias("json")
+                .with_requirement("1.0")
+                .with_source(Source::Registry("private".to_owned()))
+        );
+
+        let crate_spec: Value = toml::from_str(
+            r#"
+git = "https://github.com/example/forked"
+branch = "main"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            crate_spec_to_spec("forked", &crate_spec, Scope::Dev),
+            DependencySpec::new("forked")
+                .with_scope(Scope::Dev)
+                .with_source(Source::Git {
+                    url: "https://github.com/example/forked".to_owned(),
+                    reference: Some("main".to_owned()),
                 })
-                .map(|(crate_name, _crate_spec)| crate_name.as_ref()),
+        );
+    }
+
+    fn cargo(manifest: &str) -> Cargo {
+        toml::from_str(manifest).unwrap()
+    }
+
+    fn activated(cargo: &Cargo) -> Vec<&str> {
+        let mut activated: Vec<_> = cargo
+            .activated
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        activated.sort_unstable();
+        activated
+    }
+
+    const FEATURES: &str = r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[features]
+default = ["tls"]
+tls = ["dep:rustls", "hyper?/tls"]
+server = ["hyper/server", "json"]
+
+[dependencies]
+rustls = { version = "0.20", optional = true }
+hyper = { version = "0.14", optional = true }
+json = { version = "1.0", optional = true, package = "serde_json" }
+"#;
+
+    #[test]
+    fn activates_features() {
+        let mut app = cargo(FEATURES);
+        app.activate(false, vec!["default".to_owned()]);
+        assert_eq!(activated(&app), ["rustls"]);
+        assert!(app
+            .dependency_features
+            .contains(&("hyper".to_owned(), "tls".to_owned())));
+
+        let mut app = cargo(FEATURES);
+        app.activate(false, vec!["server".to_owned()]);
+        assert_eq!(activated(&app), ["hyper", "json"]);
+        let mut packages: Vec<_> = app.active_packages().into_iter().collect();
+        packages.sort();
+        assert_eq!(packages, ["hyper", "serde_json"]);
+
+        let mut app = cargo(FEATURES);
+        app.activate(true, Vec::new());
+        assert_eq!(app.activated, None);
+        assert_eq!(app.dependency_specs().count(), 3);
+    }
+
+    #[test]
+    fn inherits_workspace_dependencies() {
+        let inherited: Table = toml::from_str(
+            r#"
+serde = { version = "1.0", default-features = false }
+log = "0.4"
+"#,
+        )
+        .unwrap();
+        let crate_spec: Value = toml::from_str(
+            r#"
+workspace = true
+features = ["derive"]
+"#,
         )
+        .unwrap();
+        let serde = inherit("serde", &crate_spec, &inherited);
+        assert_eq!(serde.get("version").and_then(Value::as_str), Some("1.0"));
+        assert_eq!(serde.get("default-features"), Some(&Value::Boolean(false)));
+        assert!(serde.get("features").is_some());
+        assert!(serde.get("workspace").is_none());
+
+        let crate_spec: Value = toml::from_str("workspace = true").unwrap();
+        let log = inherit("log", &crate_spec, &inherited);
+        assert_eq!(log.get("version").and_then(Value::as_str), Some("0.4"));
+
+        let crate_spec = Value::String("1.0".to_owned());
+        assert_eq!(inherit("serde", &crate_spec, &inherited), crate_spec);
+    }
+
+    #[test]
+    fn unifies_member_features() {
+        let mut app = cargo(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+core = { path = "../core", default-features = false, features = ["json"] }
+
+[dev-dependencies]
+core = { path = "../core", features = ["testing"] }
+"#,
+        );
+        let mut core = cargo(
+            r#"
+[package]
+name = "core"
+version = "0.1.0"
+
+[features]
+default = ["dep:log"]
+json = ["dep:serde_json"]
+testing = ["dep:proptest"]
+
+[dependencies]
+log = { version = "0.4", optional = true }
+serde_json = { version = "1.0", optional = true }
+proptest = { version = "1.0", optional = true }
+"#,
+        );
+        app.activate(false, vec!["default".to_owned()]);
+        core.activate(false, Vec::new());
+        assert_eq!(
+            app.path_dependency_features(),
+            [("core".to_owned(), vec!["json".to_owned()])]
+        );
+
+        let mut crates = vec![app, core];
+        unify_features(&mut crates);
+        assert_eq!(activated(&crates[1]), ["serde_json"]);
     }
 }
@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_derive::Deserialize;
+
+use crate::core::{walk_scopes, DependencySpec, DependencySpecs, Scope, Source};
+use crate::rust::cargo::Cargo;
+
+const CRATES_IO_INDEX: &str = "registry+https://github.com/rust-lang/crates.io-index";
+const CRATES_IO_SPARSE_INDEX: &str = "sparse+https://index.crates.io/";
+
+/// Parse a package source, as recorded by `Cargo.lock` and `cargo metadata`.
+pub fn parse_source(source: &str) -> Option<Source> {
+    match source {
+        CRATES_IO_INDEX | CRATES_IO_SPARSE_INDEX => Some(Source::Default),
+        // Git sources are locked to a commit, as in `git+https://host/repo?branch=main#<sha>`
+        source if source.starts_with("git+") => {
+            let mut parts = source["git+".len()..].splitn(2, '#');
+            let url = parts.next()?;
+            let url = url.split('?').next()?;
+            Some(Source::Git {
+                url: url.to_owned(),
+                reference: parts.next().map(ToOwned::to_owned),
+            })
+        }
+        source if source.starts_with("path+") => None,
+        source => Some(Source::Registry(source.to_owned())),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Packages this depends on, as `<name>`, or `<name> <version>` if several are locked.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+impl Package {
+    /// Where this package was fetched from, or `None` for local path dependencies.
+    pub fn source(&self) -> Option<Source> {
+        parse_source(self.source.as_deref()?)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CargoLock {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<Package>,
+    /// Indices of the packages that are built, or `None` if every package is reported.
+    #[serde(skip)]
+    pub built: Option<HashSet<usize>>,
+}
+
+impl CargoLock {
+    /// Keep only the packages built for the crates of a workspace, following just the
+    /// dependencies their enabled features activate.
+    ///
+    /// The lockfile has the optional dependencies of every feature. Those of other packages
+    /// are all kept, as their manifests aren't read.
+    pub fn activate(&mut self, cargo: &Cargo) {
+        let active: HashMap<&str, HashSet<String>> = cargo
+            .crates()
+            .filter_map(|cargo| Some((cargo.package_name()?, cargo.active_packages())))
+            .collect();
+        let mut indices: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, package) in self.packages.iter().enumerate() {
+            indices.entry(&package.name).or_default().push(index);
+        }
+        let lookup = |dependency: &str| {
+            let mut parts = dependency.split(' ');
+            let name = parts.next()?;
+            let version = parts.next();
+            indices.get(name)?.iter().copied().find(|index| {
+                version
+                    .map(|version| self.packages[*index].version == version)
+                    .unwrap_or(true)
+            })
+        };
+        // Workspace crates are the local packages, which have no source
+        let workspace_active = |index: usize| {
+            let package = &self.packages[index];
+            match package.source {
+                Some(_) => None,
+                None => active.get(package.name.as_str()),
+            }
+        };
+
+        let roots = (0..self.packages.len())
+            .filter(|index| workspace_active(*index).is_some())
+            .map(|index| (index, Scope::Normal));
+        let built = walk_scopes(roots, |index, scope| {
+            let active = workspace_active(index);
+            self.packages[index]
+                .dependencies
+                .iter()
+                .filter(move |dependency| {
+                    let name = dependency.split(' ').next().unwrap_or_default();
+                    active.map(|active| active.contains(name)).unwrap_or(true)
+                })
+                .filter_map(|dependency| lookup(dependency))
+                .map(move |index| (index, scope))
+        });
+        self.built = Some(built.into_keys().collect());
+    }
+}
+
+impl DependencySpecs for CargoLock {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        Box::new(
+            self.packages
+                .iter()
+                .enumerate()
+                .filter(move |(index, _package)| {
+                    self.built
+                        .as_ref()
+                        .map(|built| built.contains(index))
+                        .unwrap_or(true)
+                })
+                .filter_map(|(_index, package)| {
+                    Some(
+                        DependencySpec::new(&package.name)
+                            .with_version(&package.version)
+                            .with_source(package.source()?),
+                    )
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "private", "forked"]
+
+[[package]]
+name = "serde"
+version = "1.0.130"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "f12d06de37cf59146fbdecab66aa99f9fe4f78722e3607577a5375d66bd0c913"
+
+[[package]]
+name = "private"
+version = "0.2.0"
+source = "registry+https://example.com/index"
+
+[[package]]
+name = "forked"
+version = "0.3.0"
+source = "git+https://github.com/example/forked?branch=main#0123abcd"
+"#;
+
+    #[test]
+    fn parses_sources() {
+        assert_eq!(parse_source(CRATES_IO_SPARSE_INDEX), Some(Source::Default));
+        assert_eq!(parse_source("path+file:///home/app"), None);
+        assert_eq!(
+            parse_source("sparse+https://example.com/index/"),
+            Some(Source::Registry(
+                "sparse+https://example.com/index/".to_owned()
+            ))
+        );
+        assert_eq!(
+            parse_source("git+https://github.com/example/forked#0123abcd"),
+            Some(Source::Git {
+                url: "https://github.com/example/forked".to_owned(),
+                reference: Some("0123abcd".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn skips_local_packages() {
+        let lock: CargoLock = toml::from_str(LOCK).unwrap();
+        let specs = lock.dependency_specs().collect::<Vec<_>>();
+        assert_eq!(
+            specs,
+            [
+                DependencySpec::new("serde")
+                    .with_version("1.0.130")
+                    .with_source(Source::Default),
+                DependencySpec::new("private")
+                    .with_version("0.2.0")
+                    .with_source(Source::Registry(
+                        "registry+https://example.com/index".to_owned()
+                    )),
+                DependencySpec::new("forked")
+                    .with_version("0.3.0")
+                    .with_source(Source::Git {
+                        url: "https://github.com/example/forked".to_owned(),
+                        reference: Some("0123abcd".to_owned()),
+                    }),
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_activated_dependencies() {
+        let mut lock: CargoLock = toml::from_str(
+            r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["hyper", "serde 1.0.130"]
+
+[[package]]
+name = "hyper"
+version = "0.14.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["h2"]
+
+[[package]]
+name = "h2"
+version = "0.3.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "1.0.130"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "0.9.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        )
+        .unwrap();
+        let mut cargo: Cargo = toml::from_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+hyper = { version = "0.14", optional = true }
+serde = "1.0"
+"#,
+        )
+        .unwrap();
+        cargo.activated = Some(HashSet::new());
+        lock.activate(&cargo);
+
+        let specs: Vec<_> = lock
+            .dependency_specs()
+            .map(|spec| (spec.name, spec.version))
+            .collect();
+        assert_eq!(specs, [("serde".to_owned(), Some("1.0.130".to_owned()))]);
+    }
+}
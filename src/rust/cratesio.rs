@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::AUTHORIZATION;
 use serde_derive::Deserialize;
 use url::Url;
 
@@ -7,8 +8,17 @@ use crate::core::FetchDependency;
 
 #[derive(Debug, Deserialize)]
 pub struct Version {
+    pub num: String,
     #[serde(default)]
     pub license: Option<String>,
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+impl Version {
+    fn semver(&self) -> Option<semver::Version> {
+        semver::Version::parse(&self.num).ok()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -31,25 +41,82 @@ pub struct CrateResource {
     pub versions: Vec<Version>,
 }
 
+impl CrateResource {
+    /// The exact version given, if it was published.
+    pub fn exact_version(&self, num: &str) -> Option<&Version> {
+        self.versions.iter().find(|version| version.num == num)
+    }
+
+    /// The newest version matching the requirement, as Cargo would select it, ignoring yanked versions.
+    pub fn matching_version(&self, requirement: &semver::VersionReq) -> Option<&Version> {
+        self.versions
+            .iter()
+            .filter(|version| !version.yanked)
+            .filter_map(|version| Some((version.semver()?, version)))
+            .filter(|(semver, _version)| requirement.matches(semver))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_semver, version)| version)
+    }
+
+    /// The newest stable version, ignoring yanked versions.
+    pub fn latest_version(&self) -> Option<&Version> {
+        self.matching_version(&semver::VersionReq::STAR)
+    }
+}
+
+/// Authenticate a request to a registry, with basic authentication if there is a username.
+pub fn authorize(
+    request: RequestBuilder,
+    username: Option<&str>,
+    token: Option<&str>,
+) -> RequestBuilder {
+    match (username, token) {
+        (Some(username), token) => request.basic_auth(username, token),
+        (None, Some(token)) => request.header(AUTHORIZATION, token),
+        (None, None) => request,
+    }
+}
+
 pub struct CratesIo<'a> {
     client: &'a Client,
+    api: String,
+    username: Option<String>,
+    token: Option<String>,
 }
 
 impl<'a> CratesIo<'a> {
     pub fn new(client: &'a Client) -> Self {
-        Self { client }
+        Self::with_api(client, "https://crates.io", None, None)
+    }
+
+    /// A client for another registry implementing the crates.io web API.
+    pub fn with_api(
+        client: &'a Client,
+        api: &str,
+        username: Option<&str>,
+        token: Option<&str>,
+    ) -> Self {
+        Self {
+            client,
+            api: api.trim_end_matches('/').to_owned(),
+            username: username.map(ToOwned::to_owned),
+            token: token.map(ToOwned::to_owned),
+        }
     }
 }
 
 impl<'a> FetchDependency<CrateResource> for CratesIo<'a> {
     fn fetch_dependency(&self, crate_name: &str) -> Result<CrateResource> {
-        let url = Url::parse(&format!("https://crates.io/api/v1/crates/{}", crate_name))
+        let url = Url::parse(&format!("{}/api/v1/crates/{}", self.api, crate_name))
             .with_context(|| format!("Invalid URL for rust crate '{}'.", crate_name))?;
-        self.client
-            .get(url)
-            .send()
-            .with_context(|| format!("Crates.io request for '{}' failed.", crate_name))?
-            .json()
-            .with_context(|| format!("JSON deserialization for '{}' failed.", crate_name))
+        authorize(
+            self.client.get(url),
+            self.username.as_deref(),
+            self.token.as_deref(),
+        )
+        .send()
+        .with_context(|| format!("Crates.io request for '{}' failed.", crate_name))?
+        .json()
+        .with_context(|| format!("JSON deserialization for '{}' failed.", crate_name))
     }
 }
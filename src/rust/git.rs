@@ -0,0 +1,125 @@
+use anyhow::Result;
+use log::{debug, warn};
+use toml::Value;
+
+use crate::github::{homepage_to_repo, Github};
+
+const MANIFEST_NAME: &str = "Cargo.toml";
+
+/// Package metadata read from a manifest in a git repository.
+#[derive(Debug, Default)]
+pub struct GitPackage {
+    pub version: Option<String>,
+    pub license: Option<String>,
+}
+
+/// Read a `[package]` key, following `key.workspace = true` to the workspace root.
+fn package_key(package: &Value, workspace_root: &Value, key: &str) -> Option<String> {
+    match package.get(key)? {
+        Value::String(value) => Some(value.to_owned()),
+        Value::Table(table) if table.get("workspace") == Some(&Value::Boolean(true)) => {
+            workspace_root
+                .get("workspace")?
+                .get("package")?
+                .get(key)?
+                .as_str()
+                .map(ToOwned::to_owned)
+        }
+        _ => None,
+    }
+}
+
+/// Read and parse a manifest in a Github repository.
+fn read_manifest(
+    github: &Github,
+    organisation: &str,
+    repo: &str,
+    path: &str,
+    reference: Option<&str>,
+) -> Result<Value> {
+    debug!("Reading {} in {}/{}", path, organisation, repo);
+    let contents = github.contents(organisation, repo, path, reference)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// The package in a manifest, if it is the named crate.
+fn named_package<'a>(manifest: &'a Value, crate_name: &str) -> Option<&'a Value> {
+    let package = manifest.get("package")?;
+    if package.get("name")?.as_str()? == crate_name {
+        Some(package)
+    } else {
+        None
+    }
+}
+
+/// Find the package for a crate in a Github repository, searching every manifest as Cargo does.
+///
+/// The usual workspace layouts are tried before listing the whole repository. Any failure
+/// gives `None`, so that the repository license can be used instead.
+pub fn fetch_package(
+    github: &Github,
+    url: &str,
+    reference: Option<&str>,
+    crate_name: &str,
+) -> Option<GitPackage> {
+    let (organisation, repo) = homepage_to_repo(url)?;
+    let read = |path: &str| read_manifest(github, organisation, repo, path, reference);
+    let to_package = |package: &Value, root: &Value| GitPackage {
+        version: package_key(package, root, "version"),
+        license: package_key(package, root, "license"),
+    };
+
+    let root = match read(MANIFEST_NAME) {
+        Ok(root) => root,
+        Err(error) => {
+            warn!("Could not read {} in {}: {:#}", MANIFEST_NAME, url, error);
+            return None;
+        }
+    };
+    if let Some(package) = named_package(&root, crate_name) {
+        return Some(to_package(package, &root));
+    }
+    root.get("workspace")?;
+
+    // The crate may be any member of a workspace, so try the usual layouts first
+    let probes = [
+        format!("{}/{}", crate_name, MANIFEST_NAME),
+        format!("crates/{}/{}", crate_name, MANIFEST_NAME),
+    ];
+    for path in &probes {
+        if let Some(package) = read(path)
+            .ok()
+            .as_ref()
+            .and_then(|manifest| named_package(manifest, crate_name))
+        {
+            return Some(to_package(package, &root));
+        }
+    }
+
+    // Then look through every manifest
+    let tree = match github.tree(organisation, repo, reference) {
+        Ok(tree) => tree,
+        Err(error) => {
+            warn!("Could not list files in {}: {:#}", url, error);
+            return None;
+        }
+    };
+    if tree.truncated {
+        warn!(
+            "Too many files in {} to search for rust crate '{}'",
+            url, crate_name
+        );
+        return None;
+    }
+    tree.tree
+        .iter()
+        .filter(|entry| {
+            entry.r#type == "blob"
+                && entry.path.ends_with(&format!("/{}", MANIFEST_NAME))
+                && !probes.contains(&entry.path)
+        })
+        .find_map(|entry| {
+            let manifest = read(&entry.path).ok()?;
+            named_package(&manifest, crate_name).map(|package| to_package(package, &root))
+        })
+}
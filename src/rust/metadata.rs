@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde_derive::Deserialize;
+
+use crate::core::{walk_scopes, DependencySpec, DependencySpecs, Metadata, Scope};
+use crate::rust::cargolock::parse_source;
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub license: Option<String>,
+    #[serde(default)]
+    pub license_file: Option<String>,
+    pub manifest_path: String,
+    #[serde(default)]
+    pub homepage: Option<String>,
+    #[serde(default)]
+    pub repository: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DepKind {
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NodeDep {
+    pub pkg: String,
+    #[serde(default)]
+    pub dep_kinds: Vec<DepKind>,
+}
+
+#[derive(Deserialize)]
+pub struct Node {
+    pub id: String,
+    #[serde(default)]
+    pub deps: Vec<NodeDep>,
+}
+
+#[derive(Deserialize)]
+pub struct Resolve {
+    pub nodes: Vec<Node>,
+}
+
+/// Output of `cargo metadata --format-version 1`.
+#[derive(Deserialize)]
+pub struct CargoMetadata {
+    pub packages: Vec<Package>,
+    pub workspace_members: Vec<String>,
+    #[serde(default)]
+    pub resolve: Option<Resolve>,
+}
+
+fn edge_scope(dep_kinds: &[DepKind]) -> Scope {
+    dep_kinds
+        .iter()
+        .map(|dep_kind| match dep_kind.kind.as_deref() {
+            Some("dev") => Scope::Dev,
+            Some("build") => Scope::Build,
+            _ => Scope::Normal,
+        })
+        .min()
+        // Older versions of cargo don't report kinds
+        .unwrap_or(Scope::Normal)
+}
+
+impl CargoMetadata {
+    /// Walk the resolved graph from the workspace members, finding the scope of each package.
+    ///
+    /// Anything pulled in by a build or dev dependency shares its scope.
+    fn package_scopes(&self) -> HashMap<&str, Scope> {
+        let resolve = match &self.resolve {
+            Some(resolve) => resolve,
+            None => return HashMap::new(),
+        };
+        let nodes: HashMap<&str, &Node> = resolve
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node))
+            .collect();
+        let deps = |id: &str| nodes.get(id).into_iter().flat_map(|node| &node.deps);
+
+        let roots = self
+            .workspace_members
+            .iter()
+            .flat_map(|member| deps(member))
+            .map(|dep| (dep.pkg.as_str(), edge_scope(&dep.dep_kinds)));
+        walk_scopes(roots, |id, scope| {
+            deps(id)
+                .map(|dep| (dep.pkg.as_str(), edge_scope(&dep.dep_kinds)))
+                // Dev dependencies are only built for workspace members
+                .filter(|(_pkg, dep_scope)| *dep_scope != Scope::Dev)
+                .map(move |(pkg, dep_scope)| (pkg, scope.max(dep_scope)))
+        })
+    }
+}
+
+impl DependencySpecs for CargoMetadata {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        let scopes = self.package_scopes();
+        let specs: Vec<_> = self
+            .packages
+            .iter()
+            .filter_map(|package| {
+                // Without a resolved graph, fall back to every package
+                let scope = match &self.resolve {
+                    Some(_) => *scopes.get(package.id.as_str())?,
+                    None => Scope::Normal,
+                };
+                let source = parse_source(package.source.as_deref()?)?;
+                let license_file = package.license_file.as_ref().map(|license_file| {
+                    Path::new(&package.manifest_path)
+                        .with_file_name(license_file)
+                        .display()
+                        .to_string()
+                });
+                Some(
+                    DependencySpec::new(&package.name)
+                        .with_version(&package.version)
+                        .with_scope(scope)
+                        .with_source(source)
+                        .with_metadata(Metadata {
+                            license: package.license.to_owned(),
+                            license_file,
+                            homepage: package
+                                .repository
+                                .to_owned()
+                                .or_else(|| package.homepage.to_owned()),
+                        }),
+                )
+            })
+            .collect();
+        Box::new(specs.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Source;
+
+    const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+    fn package(name: &str, source: Option<&str>) -> serde_json::Value {
+        serde_json::json!({
+            "id": format!("{} 1.0.0", name),
+            "name": name,
+            "version": "1.0.0",
+            "source": source,
+            "license": "MIT",
+            "license_file": null,
+            "manifest_path": format!("/registry/{}-1.0.0/Cargo.toml", name),
+            "repository": format!("https://github.com/example/{}", name),
+        })
+    }
+
+    fn dep(name: &str, kind: Option<&str>) -> serde_json::Value {
+        serde_json::json!({
+            "pkg": format!("{} 1.0.0", name),
+            "dep_kinds": [{ "kind": kind, "target": null }],
+        })
+    }
+
+    fn metadata() -> CargoMetadata {
+        let metadata = serde_json::json!({
+            "packages": [
+                package("app", None),
+                package("serde", Some(CRATES_IO)),
+                package("cc", Some(CRATES_IO)),
+                package("tempfile", Some(CRATES_IO)),
+                package("rand", Some(CRATES_IO)),
+                package("unused", Some(CRATES_IO)),
+            ],
+            "workspace_members": ["app 1.0.0"],
+            "resolve": {
+                "nodes": [
+                    { "id": "app 1.0.0", "deps": [
+                        dep("serde", None),
+                        dep("cc", Some("build")),
+                        dep("tempfile", Some("dev")),
+                    ]},
+                    { "id": "cc 1.0.0", "deps": [dep("serde", None)] },
+                    { "id": "tempfile 1.0.0", "deps": [dep("rand", None)] },
+                    { "id": "serde 1.0.0", "deps": [dep("unused", Some("dev"))] },
+                ],
+            },
+        });
+        serde_json::from_value(metadata).unwrap()
+    }
+
+    fn scopes(metadata: &CargoMetadata) -> Vec<(String, Scope)> {
+        let mut scopes: Vec<_> = metadata
+            .dependency_specs()
+            .map(|spec| (spec.name, spec.scope))
+            .collect();
+        scopes.sort();
+        scopes
+    }
+
+    #[test]
+    fn walks_scopes_from_workspace_members() {
+        assert_eq!(
+            scopes(&metadata()),
+            [
+                ("cc".to_owned(), Scope::Build),
+                ("rand".to_owned(), Scope::Dev),
+                ("serde".to_owned(), Scope::Normal),
+                ("tempfile".to_owned(), Scope::Dev),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_every_package_without_resolve() {
+        let mut metadata = metadata();
+        metadata.resolve = None;
+        assert_eq!(scopes(&metadata).len(), 5);
+    }
+
+    #[test]
+    fn carries_package_metadata() {
+        let spec = metadata()
+            .dependency_specs()
+            .find(|spec| spec.name == "serde")
+            .unwrap();
+        assert_eq!(spec.version.as_deref(), Some("1.0.0"));
+        assert_eq!(spec.source, Source::Default);
+        assert_eq!(
+            spec.metadata,
+            Some(Metadata {
+                license: Some("MIT".to_owned()),
+                license_file: None,
+                homepage: Some("https://github.com/example/serde".to_owned()),
+            })
+        );
+    }
+}
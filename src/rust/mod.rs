@@ -1,47 +1,386 @@
-use anyhow::Result;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 
-use crate::core::{Dependency, DependencyOverrides, FetchDependency, Resolve};
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use reqwest::blocking::Client;
+use serde_derive::Deserialize;
+
+use crate::core::{
+    load_json_file, load_toml_file, Dependency, DependencyOverrides, DependencySpec,
+    DependencySpecs, FetchDependency, Metadata, Registries, Resolve, RustLanguage, Source,
+};
+use crate::github::Github;
 
 pub mod cargo;
+pub mod cargolock;
 pub mod cratesio;
+pub mod git;
+pub mod metadata;
+pub mod sparse;
+pub mod vendor;
+
+use cargo::Cargo;
+use cargolock::CargoLock;
+use cratesio::{Crate, CrateResource, CratesIo, Version};
+use metadata::CargoMetadata;
+use sparse::SparseIndex;
+use vendor::{Vendor, VendoredPackage};
+
+const LOCKFILE_NAME: &str = "Cargo.lock";
+const MANIFEST_NAME: &str = "Cargo.toml";
+
+/// A Rust manifest, either declaring dependencies or pinning them.
+pub enum Manifest {
+    Cargo(Box<Cargo>),
+    CargoLock(CargoLock),
+    CargoMetadata(CargoMetadata),
+}
+
+impl Manifest {
+    /// Load the manifest at the given path, or the lockfile for it if configured.
+    ///
+    /// `cargo metadata` output is read from JSON files, or from stdin if the path is `-`.
+    pub fn load(path: &Path, language: &RustLanguage) -> Result<Self> {
+        if path == Path::new("-") {
+            let mut buffer = String::new();
+            io::stdin().read_to_string(&mut buffer)?;
+            let cargo_metadata = serde_json::from_str(&buffer)
+                .with_context(|| "Reading cargo metadata from stdin".to_owned())?;
+            return Ok(Manifest::CargoMetadata(cargo_metadata));
+        }
+        if path.extension().map(|extension| extension == "json") == Some(true) {
+            return Ok(Manifest::CargoMetadata(load_json_file(path)?));
+        }
+
+        if language.language.lockfile && !is_lockfile(path) {
+            let lockfile_path = find_lockfile(path)
+                .ok_or_else(|| anyhow!("No {} found for {}", LOCKFILE_NAME, path.display()))?;
+            info!("Using lockfile {}", lockfile_path.display());
+            return load_lockfile(&lockfile_path, path, language);
+        }
+
+        if is_lockfile(path) {
+            load_lockfile(path, &path.with_file_name(MANIFEST_NAME), language)
+        } else {
+            Ok(Manifest::Cargo(Box::new(Cargo::load(path, language)?)))
+        }
+    }
+}
+
+impl DependencySpecs for Manifest {
+    fn dependency_specs<'a>(&'a self) -> Box<dyn Iterator<Item = DependencySpec> + 'a> {
+        match self {
+            Manifest::Cargo(cargo) => cargo.dependency_specs(),
+            Manifest::CargoLock(cargo_lock) => cargo_lock.dependency_specs(),
+            Manifest::CargoMetadata(cargo_metadata) => cargo_metadata.dependency_specs(),
+        }
+    }
+}
+
+/// Load a lockfile, keeping the packages built with the features enabled on the manifest.
+///
+/// Every package is kept if there is no manifest.
+fn load_lockfile(
+    lockfile_path: &Path,
+    manifest_path: &Path,
+    language: &RustLanguage,
+) -> Result<Manifest> {
+    let mut cargo_lock: CargoLock = load_toml_file(lockfile_path)?;
+    if manifest_path.is_file() {
+        cargo_lock.activate(&Cargo::load(manifest_path, language)?);
+    } else {
+        warn!(
+            "No {} beside {}, so optional dependencies are reported whatever the features",
+            MANIFEST_NAME,
+            lockfile_path.display()
+        );
+    }
+    Ok(Manifest::CargoLock(cargo_lock))
+}
+
+fn is_lockfile(path: &Path) -> bool {
+    path.file_name()
+        .map(|file_name| file_name == LOCKFILE_NAME)
+        .unwrap_or(false)
+}
+
+/// Cargo keeps a single lockfile at the workspace root, so search upwards from the manifest.
+fn find_lockfile(manifest_path: &Path) -> Option<PathBuf> {
+    manifest_path
+        .parent()?
+        .ancestors()
+        .map(|directory| directory.join(LOCKFILE_NAME))
+        .find(|lockfile_path| lockfile_path.is_file())
+}
+
+/// Select the locked version, or the version Cargo would choose for the requirement.
+fn select_version<'a>(package: &'a CrateResource, spec: &DependencySpec) -> Option<&'a Version> {
+    if let Some(num) = &spec.version {
+        return package.exact_version(num);
+    }
+
+    let requirement = spec.requirement.as_ref().and_then(|requirement| {
+        semver::VersionReq::parse(requirement)
+            .map_err(|error| {
+                warn!(
+                    "Invalid version requirement '{}' for rust crate '{}': {}",
+                    requirement, spec.name, error
+                )
+            })
+            .ok()
+    });
+    match requirement {
+        Some(requirement) => package.matching_version(&requirement).or_else(|| {
+            warn!(
+                "No version of rust crate '{}' matches '{}', using latest",
+                spec.name, requirement
+            );
+            package.latest_version()
+        }),
+        None => package.latest_version(),
+    }
+}
 
-use cratesio::{Crate, CrateResource, CratesIo};
+#[derive(Deserialize)]
+struct CargoConfig {
+    #[serde(default)]
+    registries: Registries,
+}
+
+/// Registries from the language config, and any Cargo configuration files it lists.
+pub fn load_registries(language: &RustLanguage) -> Result<Registries> {
+    let mut registries = Registries::new();
+    for cargo_config_path in &language.cargo_config {
+        let cargo_config: CargoConfig = load_toml_file(cargo_config_path)?;
+        registries.extend(cargo_config.registries);
+    }
+    registries.extend(language.language.registries.clone());
+    Ok(registries)
+}
+
+/// Clients for each registry, skipping those which don't use the sparse protocol.
+///
+/// Cargo configuration may define registries which are never used, so these are only
+/// an error if a dependency is published to one.
+pub fn load_indexes<'a>(
+    client: &'a Client,
+    language: &RustLanguage,
+) -> Result<HashMap<String, SparseIndex<'a>>> {
+    let mut indexes = HashMap::new();
+    for (name, registry) in load_registries(language)? {
+        if !registry.index.starts_with("sparse+") {
+            warn!(
+                "Skipping rust registry '{}', which does not use the sparse protocol",
+                name
+            );
+            continue;
+        }
+        let index = SparseIndex::new(client, &name, &registry)?;
+        indexes.insert(name, index);
+    }
+    Ok(indexes)
+}
 
 pub struct Resolver<'a> {
     overrides: &'a DependencyOverrides,
     cratesio: &'a CratesIo<'a>,
+    registries: &'a HashMap<String, SparseIndex<'a>>,
+    github: &'a Github<'a>,
+    vendor: Option<&'a Vendor>,
 }
 
 impl<'a> Resolver<'a> {
-    pub fn new(overrides: &'a DependencyOverrides, cratesio: &'a CratesIo) -> Self {
+    pub fn new(
+        overrides: &'a DependencyOverrides,
+        cratesio: &'a CratesIo,
+        registries: &'a HashMap<String, SparseIndex>,
+        github: &'a Github,
+    ) -> Self {
         Self {
             overrides,
             cratesio,
+            registries,
+            github,
+            vendor: None,
         }
     }
+
+    /// Read crates from sources on disk, rather than making requests.
+    pub fn with_vendor(mut self, vendor: &'a Vendor) -> Self {
+        self.vendor = Some(vendor);
+        self
+    }
+
+    /// Find a registry by name, as in `Cargo.toml`, or by index URL, as in `Cargo.lock`.
+    fn registry(&self, key: &str) -> Result<&SparseIndex<'a>> {
+        self.registries
+            .get(key)
+            .or_else(|| {
+                self.registries
+                    .values()
+                    .find(|registry| registry.is_index(key))
+            })
+            .ok_or_else(|| anyhow!("No configuration found for rust registry '{}'", key))
+    }
 }
 
-impl<'a> Resolve for Resolver<'a> {
-    fn resolve(&self, name: &str) -> Result<Dependency> {
-        let package = self.cratesio.fetch_dependency(name)?;
+impl<'a> Resolver<'a> {
+    fn resolve_registry(&self, spec: &DependencySpec) -> Result<Dependency> {
+        let registry = match &spec.source {
+            Source::Registry(key) => Some(self.registry(key)?),
+            _ => None,
+        };
+        let package = match registry {
+            Some(registry) => registry.fetch_dependency(&spec.name)?,
+            None => self.cratesio.fetch_dependency(&spec.name)?,
+        };
 
-        let CrateResource { crate_, versions } = package;
+        let version = select_version(&package, spec);
+        let (version, mut license) = match version {
+            Some(version) => (Some(version.num.to_owned()), version.license.to_owned()),
+            None => {
+                warn!("No published version found for rust crate '{}'", spec.name);
+                (spec.version.to_owned(), None)
+            }
+        };
 
-        let license = versions
-            .get(0)
-            .expect("Rust crate must have at least one version.")
-            .license
-            .to_owned();
+        let CrateResource { crate_, .. } = package;
+        let url = match registry {
+            Some(registry) => registry.url(&crate_.name),
+            None => crate_.url(),
+        };
+        let Crate { name, mut homepage } = crate_;
 
-        let url = crate_.url();
-        let Crate { name, homepage, .. } = crate_;
+        // Without a web API, package metadata is only found in the published crate
+        if let (Some(registry), Some(num), None) = (registry, &version, &license) {
+            let package = registry.fetch_package(&name, num)?;
+            license = package.license;
+            homepage = homepage.or(package.homepage).or(package.repository);
+        }
 
-        let mut dependency = Dependency {
+        Ok(Dependency {
             category: "rust",
             name,
+            alias: spec.alias.to_owned(),
+            version,
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
             url,
             license,
+            license_file: None,
             homepage,
+        })
+    }
+
+    /// Read the license from the crate's own manifest in the repository.
+    ///
+    /// If the license is not found, the repository is used as the homepage so that
+    /// the repository license can be used instead.
+    fn resolve_git(
+        &self,
+        spec: &DependencySpec,
+        url: &str,
+        reference: Option<&str>,
+    ) -> Result<Dependency> {
+        let package = git::fetch_package(self.github, url, reference, &spec.name);
+        if package.is_none() {
+            warn!(
+                "No manifest found for rust crate '{}' in {}",
+                spec.name, url
+            );
+        }
+        let package = package.unwrap_or_default();
+
+        Ok(Dependency {
+            category: "rust",
+            name: spec.name.to_owned(),
+            alias: spec.alias.to_owned(),
+            version: package.version.or_else(|| spec.version.to_owned()),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            url: url.to_owned(),
+            license: package.license,
+            license_file: None,
+            homepage: Some(url.to_owned()),
+        })
+    }
+
+    /// Use metadata already given by the manifest.
+    /// Where a crate is published, for crates that aren't fetched from their registry.
+    fn source_url(&self, spec: &DependencySpec) -> String {
+        match &spec.source {
+            Source::Default => format!("https://crates.io/crates/{}", spec.name),
+            Source::Registry(key) => match self.registry(key) {
+                Ok(registry) => registry.url(&spec.name),
+                Err(_) => key.to_owned(),
+            },
+            Source::Git { url, .. } => url.to_owned(),
+        }
+    }
+
+    fn resolve_known(&self, spec: &DependencySpec) -> Dependency {
+        let Metadata {
+            license,
+            license_file,
+            homepage,
+        } = spec.metadata.to_owned().unwrap_or_default();
+
+        Dependency {
+            category: "rust",
+            name: spec.name.to_owned(),
+            alias: spec.alias.to_owned(),
+            version: spec.version.to_owned(),
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            url: self.source_url(spec),
+            license,
+            license_file,
+            homepage,
+        }
+    }
+
+    fn resolve_vendored(&self, spec: &DependencySpec, vendor: &Vendor) -> Dependency {
+        let vendored = vendor.find_package(spec);
+        if vendored.is_none() {
+            warn!("No vendored source found for rust crate '{}'", spec.name);
+        }
+
+        let (version, license, license_file) = match vendored {
+            Some(VendoredPackage { package, directory }) => {
+                let license_file = package
+                    .license_file
+                    .map(|license_file| directory.join(license_file).display().to_string());
+                (Some(package.version), package.license, license_file)
+            }
+            None => (spec.version.to_owned(), None, None),
+        };
+
+        Dependency {
+            category: "rust",
+            name: spec.name.to_owned(),
+            alias: spec.alias.to_owned(),
+            version,
+            scope: spec.scope,
+            workspace: spec.workspace.to_owned(),
+            url: self.source_url(spec),
+            license,
+            license_file,
+            homepage: None,
+        }
+    }
+}
+
+impl<'a> Resolve for Resolver<'a> {
+    fn resolve(&self, spec: &DependencySpec) -> Result<Dependency> {
+        let mut dependency = match (&spec.source, self.vendor) {
+            _ if spec.metadata.is_some() => self.resolve_known(spec),
+            (_, Some(vendor)) => self.resolve_vendored(spec, vendor),
+            (Source::Git { url, reference }, None) => {
+                self.resolve_git(spec, url, reference.as_deref())?
+            }
+            _ => self.resolve_registry(spec)?,
         };
 
         if let Some(dependency_override) = self.overrides.get(&dependency.name) {
@@ -0,0 +1,302 @@
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::env;
+use std::io::Read;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+use log::debug;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde_derive::Deserialize;
+use url::Url;
+
+use crate::core::{FetchDependency, Registry};
+use crate::rust::cargo::{Package, PublishedCargo};
+use crate::rust::cratesio::{authorize, Crate, CrateResource, CratesIo, Version};
+
+/// The `config.json` at the root of a registry index.
+#[derive(Debug, Deserialize)]
+pub struct IndexConfig {
+    pub dl: String,
+    #[serde(default)]
+    pub api: Option<String>,
+}
+
+/// A single published version, as listed in the index file of a crate.
+#[derive(Debug, Deserialize)]
+pub struct IndexEntry {
+    pub name: String,
+    pub vers: String,
+    #[serde(default)]
+    pub cksum: String,
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+/// A registry served over Cargo's sparse index protocol.
+pub struct SparseIndex<'a> {
+    client: &'a Client,
+    index: Url,
+    token: Option<String>,
+    username: Option<String>,
+    config: OnceCell<IndexConfig>,
+    /// Index files already fetched, by crate name.
+    entries: RefCell<HashMap<String, Rc<Vec<IndexEntry>>>>,
+}
+
+impl<'a> SparseIndex<'a> {
+    pub fn new(client: &'a Client, name: &str, registry: &Registry) -> Result<Self> {
+        let index = registry.index.strip_prefix("sparse+").ok_or_else(|| {
+            anyhow!(
+                "Registry '{}' must use the sparse protocol, found '{}'.",
+                name,
+                registry.index
+            )
+        })?;
+        let index = Url::parse(&format!("{}/", index.trim_end_matches('/')))
+            .with_context(|| format!("Invalid index URL for registry '{}'.", name))?;
+
+        // Cargo reads registry tokens from the environment in the same way
+        let token_variable = format!(
+            "CARGO_REGISTRIES_{}_TOKEN",
+            name.to_uppercase().replace('-', "_")
+        );
+        let token = registry
+            .token
+            .to_owned()
+            .or_else(|| env::var(token_variable).ok());
+
+        Ok(Self {
+            client,
+            index,
+            token,
+            username: registry.username.to_owned(),
+            config: OnceCell::new(),
+            entries: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Whether this registry is the one at the given index URL, as recorded in `Cargo.lock`.
+    pub fn is_index(&self, index: &str) -> bool {
+        let index = index
+            .trim_start_matches("sparse+")
+            .trim_start_matches("registry+")
+            .trim_end_matches('/');
+        self.index.as_str().trim_end_matches('/') == index
+    }
+
+    /// The index file listing every published version of a crate.
+    pub fn url(&self, crate_name: &str) -> String {
+        format!("{}{}", self.index, index_path(crate_name))
+    }
+
+    fn get(&self, url: Url) -> RequestBuilder {
+        authorize(
+            self.client.get(url),
+            self.username.as_deref(),
+            self.token.as_deref(),
+        )
+    }
+
+    pub fn config(&self) -> Result<&IndexConfig> {
+        if let Some(config) = self.config.get() {
+            return Ok(config);
+        }
+        let url = self.index.join("config.json")?;
+        let config = self
+            .get(url)
+            .send()
+            .with_context(|| format!("Index request for '{}' failed.", self.index))?
+            .error_for_status()?
+            .json()
+            .with_context(|| format!("JSON deserialization for '{}' failed.", self.index))?;
+        Ok(self.config.get_or_init(|| config))
+    }
+
+    pub fn entries(&self, crate_name: &str) -> Result<Rc<Vec<IndexEntry>>> {
+        if let Some(entries) = self.entries.borrow().get(crate_name) {
+            return Ok(Rc::clone(entries));
+        }
+        let url = Url::parse(&self.url(crate_name))
+            .with_context(|| format!("Invalid URL for rust crate '{}'.", crate_name))?;
+        let body = self
+            .get(url)
+            .send()
+            .with_context(|| format!("Index request for '{}' failed.", crate_name))?
+            .error_for_status()?
+            .text()?;
+        let entries = body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("JSON deserialization for '{}' failed.", crate_name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let entries = Rc::new(entries);
+        self.entries
+            .borrow_mut()
+            .insert(crate_name.to_owned(), Rc::clone(&entries));
+        Ok(entries)
+    }
+
+    /// Download a published crate, and read the package metadata from its manifest.
+    pub fn fetch_package(&self, crate_name: &str, version: &str) -> Result<Package> {
+        let entries = self.entries(crate_name)?;
+        let entry = entries
+            .iter()
+            .find(|entry| entry.vers == version)
+            .ok_or_else(|| anyhow!("Version {} of '{}' not in index.", version, crate_name))?;
+        let url = Url::parse(&download_url(&self.config()?.dl, entry))
+            .with_context(|| format!("Invalid download URL for rust crate '{}'.", crate_name))?;
+        let body = self
+            .get(url)
+            .send()
+            .with_context(|| format!("Download of '{}' failed.", crate_name))?
+            .error_for_status()?
+            .bytes()?;
+
+        let manifest_path = format!("{}-{}/Cargo.toml", entry.name, entry.vers);
+        let mut archive = tar::Archive::new(GzDecoder::new(body.as_ref()));
+        for file in archive.entries()? {
+            let mut file = file?;
+            if file.path()?.to_string_lossy() == manifest_path {
+                let mut buffer = String::new();
+                file.read_to_string(&mut buffer)?;
+                let manifest: PublishedCargo = toml::from_str(&buffer)
+                    .with_context(|| format!("With path {}", manifest_path))?;
+                return Ok(manifest.package);
+            }
+        }
+        Err(anyhow!("No manifest found in crate '{}'.", crate_name))
+    }
+}
+
+impl<'a> FetchDependency<CrateResource> for SparseIndex<'a> {
+    fn fetch_dependency(&self, crate_name: &str) -> Result<CrateResource> {
+        if let Some(api) = &self.config()?.api {
+            match CratesIo::with_api(
+                self.client,
+                api,
+                self.username.as_deref(),
+                self.token.as_deref(),
+            )
+            .fetch_dependency(crate_name)
+            {
+                Ok(crate_resource) => return Ok(crate_resource),
+                Err(error) => debug!("Falling back to index for '{}': {:#}", crate_name, error),
+            }
+        }
+
+        // The index only has versions. Licenses are read from the published crate later.
+        let versions = self
+            .entries(crate_name)?
+            .iter()
+            .map(|entry| Version {
+                num: entry.vers.to_owned(),
+                license: None,
+                yanked: entry.yanked,
+            })
+            .collect();
+        Ok(CrateResource {
+            crate_: Crate {
+                name: crate_name.to_owned(),
+                homepage: None,
+            },
+            versions,
+        })
+    }
+}
+
+/// The directory of a crate's index file, as defined by Cargo.
+fn index_prefix(crate_name: &str) -> String {
+    match crate_name.len() {
+        1 => "1".to_owned(),
+        2 => "2".to_owned(),
+        3 => format!("3/{}", &crate_name[..1]),
+        _ => format!("{}/{}", &crate_name[..2], &crate_name[2..4]),
+    }
+}
+
+fn index_path(crate_name: &str) -> String {
+    let crate_name = crate_name.to_lowercase();
+    format!("{}/{}", index_prefix(&crate_name), crate_name)
+}
+
+fn download_url(dl: &str, entry: &IndexEntry) -> String {
+    const MARKERS: [&str; 5] = [
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+    if !MARKERS.iter().any(|marker| dl.contains(marker)) {
+        return format!("{}/{}/{}/download", dl, entry.name, entry.vers);
+    }
+    dl.replace("{crate}", &entry.name)
+        .replace("{version}", &entry.vers)
+        .replace("{prefix}", &index_prefix(&entry.name))
+        .replace("{lowerprefix}", &index_prefix(&entry.name.to_lowercase()))
+        .replace("{sha256-checksum}", &entry.cksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> IndexEntry {
+        IndexEntry {
+            name: name.to_owned(),
+            vers: "1.0.0".to_owned(),
+            cksum: "abc123".to_owned(),
+            yanked: false,
+        }
+    }
+
+    #[test]
+    fn prefixes_index_paths() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("abc"), "3/a/abc");
+        assert_eq!(index_path("Serde"), "se/rd/serde");
+        assert_eq!(index_prefix("Serde"), "Se/rd");
+    }
+
+    #[test]
+    fn builds_download_urls() {
+        assert_eq!(
+            download_url("https://example.com/api/v1/crates", &entry("serde")),
+            "https://example.com/api/v1/crates/serde/1.0.0/download"
+        );
+        assert_eq!(
+            download_url(
+                "https://example.com/{prefix}/{lowerprefix}/{crate}-{version}.crate?{sha256-checksum}",
+                &entry("Serde")
+            ),
+            "https://example.com/Se/rd/se/rd/Serde-1.0.0.crate?abc123"
+        );
+    }
+
+    #[test]
+    fn matches_index_urls() {
+        let client = Client::new();
+        let registry = Registry {
+            index: "sparse+https://example.com/index".to_owned(),
+            token: None,
+            username: None,
+        };
+        let index = SparseIndex::new(&client, "private", &registry).unwrap();
+        assert!(index.is_index("sparse+https://example.com/index/"));
+        assert!(!index.is_index("sparse+https://example.com/other/"));
+        assert_eq!(index.url("serde"), "https://example.com/index/se/rd/serde");
+
+        let registry = Registry {
+            index: "https://example.com/index".to_owned(),
+            token: None,
+            username: None,
+        };
+        assert!(SparseIndex::new(&client, "private", &registry).is_err());
+    }
+}
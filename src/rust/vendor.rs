@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+
+use crate::core::{load_toml_file, DependencySpec};
+use crate::rust::cargo::{Package, PublishedCargo};
+
+const MANIFEST_NAME: &str = "Cargo.toml";
+
+/// Crate sources on disk, such as from `cargo vendor` or the Cargo registry cache.
+pub struct Vendor {
+    /// Directories containing a crate manifest, by the crate name their directory is named for.
+    crate_directories: HashMap<String, Vec<PathBuf>>,
+}
+
+/// A crate found on disk, with the directory it was found in.
+pub struct VendoredPackage {
+    pub package: Package,
+    pub directory: PathBuf,
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(relative), Some(home)) => Path::new(&home).join(relative),
+        _ => path.to_owned(),
+    }
+}
+
+fn subdirectories(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut subdirectories = Vec::new();
+    for entry in fs::read_dir(directory)
+        .with_context(|| format!("Reading directory {}", directory.display()))?
+    {
+        let path = entry?.path();
+        if path.is_dir() {
+            subdirectories.push(path);
+        }
+    }
+    subdirectories.sort();
+    Ok(subdirectories)
+}
+
+/// The crate a directory holds, which is either named `<name>` or `<name>-<version>`.
+fn directory_crate_name(directory_name: &str) -> &str {
+    directory_name
+        .match_indices('-')
+        .map(|(index, _separator)| index)
+        .find(|index| semver::Version::parse(&directory_name[index + 1..]).is_ok())
+        .map(|index| &directory_name[..index])
+        .unwrap_or(directory_name)
+}
+
+impl Vendor {
+    pub fn new(paths: &[PathBuf]) -> Result<Self> {
+        let mut directories = Vec::new();
+        for path in paths {
+            let path = expand_home(path);
+            // The registry cache has a directory per index, like `index.crates.io-<hash>`
+            for subdirectory in subdirectories(&path)? {
+                if !subdirectory.join(MANIFEST_NAME).is_file() {
+                    directories.push(subdirectory);
+                }
+            }
+            directories.push(path);
+        }
+
+        let mut crate_directories: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for directory in directories {
+            for crate_directory in subdirectories(&directory)? {
+                if !crate_directory.join(MANIFEST_NAME).is_file() {
+                    continue;
+                }
+                let crate_name = match crate_directory.file_name() {
+                    Some(directory_name) => {
+                        directory_crate_name(&directory_name.to_string_lossy()).to_owned()
+                    }
+                    None => continue,
+                };
+                crate_directories
+                    .entry(crate_name)
+                    .or_default()
+                    .push(crate_directory);
+            }
+        }
+        Ok(Self { crate_directories })
+    }
+
+    /// Read the crates with the given name, skipping any manifest that can't be read.
+    fn candidates(&self, crate_name: &str) -> Vec<VendoredPackage> {
+        let crate_directories = self.crate_directories.get(crate_name).into_iter().flatten();
+        let mut candidates = Vec::new();
+        for crate_directory in crate_directories {
+            let manifest_path = crate_directory.join(MANIFEST_NAME);
+            debug!("Reading {}", manifest_path.display());
+            let manifest: PublishedCargo = match load_toml_file(&manifest_path) {
+                Ok(manifest) => manifest,
+                Err(error) => {
+                    warn!("Skipping vendored rust crate: {:#}", error);
+                    continue;
+                }
+            };
+            if manifest.package.name == crate_name {
+                candidates.push(VendoredPackage {
+                    package: manifest.package,
+                    directory: crate_directory.to_owned(),
+                });
+            }
+        }
+        candidates
+    }
+
+    /// The locked version of a crate, or the newest version matching the requirement.
+    pub fn find_package(&self, spec: &DependencySpec) -> Option<VendoredPackage> {
+        let candidates = self.candidates(&spec.name);
+        if let Some(version) = &spec.version {
+            return candidates
+                .into_iter()
+                .find(|candidate| &candidate.package.version == version);
+        }
+
+        let requirement = spec
+            .requirement
+            .as_ref()
+            .and_then(|requirement| semver::VersionReq::parse(requirement).ok())
+            .unwrap_or(semver::VersionReq::STAR);
+        candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let version = semver::Version::parse(&candidate.package.version).ok()?;
+                Some((version, candidate))
+            })
+            .filter(|(version, _candidate)| requirement.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_version, candidate)| candidate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_crate(directory: &Path, manifest: &str) {
+        fs::create_dir_all(directory).unwrap();
+        fs::write(directory.join(MANIFEST_NAME), manifest).unwrap();
+    }
+
+    #[test]
+    fn names_crate_directories() {
+        assert_eq!(directory_crate_name("serde"), "serde");
+        assert_eq!(directory_crate_name("serde-1.0.130"), "serde");
+        assert_eq!(directory_crate_name("sha-1"), "sha-1");
+        assert_eq!(directory_crate_name("sha-1-0.9.8"), "sha-1");
+        assert_eq!(
+            directory_crate_name("pin-project-1.0.0-alpha.1"),
+            "pin-project"
+        );
+    }
+
+    #[test]
+    fn finds_vendored_versions() {
+        let directory = env::temp_dir().join(format!("yalich-vendor-{}", std::process::id()));
+        write_crate(
+            &directory.join("vendor/serde"),
+            "[package]\nname = \"serde\"\nversion = \"1.0.130\"\nlicense = \"MIT OR Apache-2.0\"\n",
+        );
+        write_crate(
+            &directory.join("vendor/serde-0.9.15"),
+            "[package]\nname = \"serde\"\nversion = \"0.9.15\"\n",
+        );
+        write_crate(
+            &directory.join("registry/index.crates.io-1949cf8c6b5b557f/serde-1.0.200"),
+            "[package]\nname = \"serde\"\nversion = \"1.0.200\"\n",
+        );
+        write_crate(
+            &directory.join("vendor/serde-1.0.0"),
+            "[package\nname = \"serde\"\n",
+        );
+
+        let vendor = Vendor::new(&[directory.join("vendor"), directory.join("registry")]).unwrap();
+        let version = |spec: &DependencySpec| {
+            vendor
+                .find_package(spec)
+                .map(|vendored| vendored.package.version)
+        };
+        let locked = DependencySpec::new("serde").with_version("0.9.15");
+        let required = DependencySpec::new("serde").with_requirement("1.0");
+        let pinned = DependencySpec::new("serde").with_requirement("=1.0.130");
+        let broken = DependencySpec::new("serde").with_version("1.0.0");
+        let (locked, required, pinned, broken) = (
+            version(&locked),
+            version(&required),
+            version(&pinned),
+            version(&broken),
+        );
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(locked.as_deref(), Some("0.9.15"));
+        assert_eq!(required.as_deref(), Some("1.0.200"));
+        assert_eq!(pinned.as_deref(), Some("1.0.130"));
+        assert_eq!(broken, None);
+    }
+}